@@ -0,0 +1,179 @@
+//! First-fit allocator over one thread's heap region.
+//!
+//! The region starts with a `Heap` header; the rest is the arena.  Free
+//! blocks form an address-ordered singly linked list and are merged with
+//! their neighbours on free.  Every allocation is preceded by a `Used` tag
+//! recording the whole block it came from, so padding for large alignments
+//! goes back to the free list with it.
+
+use core::alloc::Layout;
+use core::mem::size_of;
+use core::ptr::null_mut;
+
+const MAGIC: u32 = 0x4845_4150; // "HEAP"
+const GRANULE: usize = 8;
+
+/// Usage of one thread heap.
+#[derive(Copy, Clone, defmt::Format)]
+pub struct HeapStats {
+    pub size: usize,
+    pub used: usize,
+    pub free: usize,
+    pub peak: usize,
+    pub allocs: u32,
+    pub frees: u32,
+    pub failed: u32,
+}
+
+#[repr(C)]
+struct Free {
+    size: usize,
+    next: *mut Free,
+}
+
+#[repr(C)]
+struct Used {
+    start: usize,
+    end: usize,
+}
+
+const MIN_BLOCK: usize = size_of::<Free>() + GRANULE;
+
+#[repr(C)]
+pub(crate) struct Heap {
+    magic: u32,
+    free: *mut Free,
+    arena_start: usize,
+    arena_end: usize,
+    used: usize,
+    peak: usize,
+    allocs: u32,
+    frees: u32,
+    failed: u32,
+}
+
+fn align_up(x: usize, align: usize) -> usize {
+    (x + align - 1) & !(align - 1)
+}
+
+impl Heap {
+    /// The heap living at `base`, set up on first use.  The kernel clears the
+    /// magic whenever the slot is handed to a new thread.
+    pub(crate) unsafe fn from_region(base: usize, size: usize) -> &'static mut Heap {
+        let heap = &mut *(base as *mut Heap);
+        if heap.magic != MAGIC {
+            let arena_start = align_up(base + size_of::<Heap>(), GRANULE);
+            let arena_end = (base + size) & !(GRANULE - 1);
+            let first = arena_start as *mut Free;
+            first.write(Free { size: arena_end - arena_start, next: null_mut() });
+
+            *heap = Heap {
+                magic: MAGIC,
+                free: first,
+                arena_start,
+                arena_end,
+                used: 0,
+                peak: 0,
+                allocs: 0,
+                frees: 0,
+                failed: 0,
+            };
+        }
+        heap
+    }
+
+    pub(crate) unsafe fn alloc(&mut self, layout: Layout) -> *mut u8 {
+        let align = layout.align().max(GRANULE);
+        let size = align_up(layout.size().max(1), GRANULE);
+
+        let mut prev: *mut Free = null_mut();
+        let mut cur = self.free;
+        while !cur.is_null() {
+            let start = cur as usize;
+            let end = start + (*cur).size;
+            let payload = align_up(start + size_of::<Used>(), align);
+            let alloc_end = payload + size;
+
+            if alloc_end <= end {
+                let next = (*cur).next;
+
+                // split off the tail if it's worth keeping
+                let block_end = if end - alloc_end >= MIN_BLOCK {
+                    let tail = alloc_end as *mut Free;
+                    tail.write(Free { size: end - alloc_end, next });
+                    self.unlink(prev, tail);
+                    alloc_end
+                } else {
+                    self.unlink(prev, next);
+                    end
+                };
+
+                ((payload - size_of::<Used>()) as *mut Used).write(Used { start, end: block_end });
+
+                self.used += block_end - start;
+                self.peak = self.peak.max(self.used);
+                self.allocs += 1;
+                return payload as *mut u8;
+            }
+
+            prev = cur;
+            cur = (*cur).next;
+        }
+
+        self.failed += 1;
+        null_mut()
+    }
+
+    pub(crate) unsafe fn dealloc(&mut self, ptr: *mut u8, _layout: Layout) {
+        let tag = (ptr as usize - size_of::<Used>()) as *const Used;
+        let Used { start, end } = tag.read();
+        debug_assert!(start >= self.arena_start && end <= self.arena_end && start < end);
+
+        self.used -= end - start;
+        self.frees += 1;
+
+        // find the neighbours in the address-ordered list
+        let mut prev: *mut Free = null_mut();
+        let mut next = self.free;
+        while !next.is_null() && (next as usize) < start {
+            prev = next;
+            next = (*next).next;
+        }
+
+        let block = start as *mut Free;
+        block.write(Free { size: end - start, next });
+        self.unlink(prev, block);
+
+        // merge with the following block, then with the preceding one
+        if !next.is_null() && end == next as usize {
+            (*block).size += (*next).size;
+            (*block).next = (*next).next;
+        }
+        if !prev.is_null() && prev as usize + (*prev).size == start {
+            (*prev).size += (*block).size;
+            (*prev).next = (*block).next;
+        }
+    }
+
+    /// Point `prev.next` (or the list head) at `to`.
+    unsafe fn unlink(&mut self, prev: *mut Free, to: *mut Free) {
+        if prev.is_null() {
+            self.free = to;
+        } else {
+            (*prev).next = to;
+        }
+    }
+
+    pub(crate) fn stats(&self) -> HeapStats {
+        let size = self.arena_end - self.arena_start;
+        HeapStats {
+            size,
+            used: self.used,
+            free: size - self.used,
+            peak: self.peak,
+            allocs: self.allocs,
+            frees: self.frees,
+            failed: self.failed,
+        }
+    }
+}
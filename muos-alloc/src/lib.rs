@@ -0,0 +1,57 @@
+//! Per-thread heaps for muos threads.
+//!
+//! Every thread spawned with `ThreadAttrs { heap: true, .. }` gets a private
+//! heap that the kernel maps into the MPU next to its stack.  `ThreadHeapAlloc`
+//! routes each allocation to the heap of whichever thread is running, without
+//! a syscall: the kernel publishes that heap's place on every switch, in a
+//! word pair every thread may read (`muos_threads::heap::current`).
+//! Allocator state lives in the heap itself, so an unprivileged thread never
+//! touches memory it doesn't own.
+//!
+//! ```ignore
+//! extern crate alloc;
+//!
+//! #[global_allocator]
+//! static ALLOC: muos_alloc::ThreadHeapAlloc = muos_alloc::ThreadHeapAlloc;
+//! ```
+//!
+//! Allocating from interrupt handlers isn't supported: they'd land in
+//! whatever thread's heap happens to be mapped.
+
+#![no_std]
+
+mod heap;
+
+use core::alloc::{GlobalAlloc, Layout};
+
+pub use crate::heap::HeapStats;
+use crate::heap::Heap;
+
+/// The running thread's heap, as the kernel last mapped it.
+fn current_heap() -> Option<&'static mut Heap> {
+    muos_threads::heap::current().map(|(base, size)| unsafe { Heap::from_region(base, size) })
+}
+
+/// `GlobalAlloc` over the running thread's private heap.
+pub struct ThreadHeapAlloc;
+
+unsafe impl GlobalAlloc for ThreadHeapAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match current_heap() {
+            Some(heap) => heap.alloc(layout),
+            None => core::ptr::null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        match current_heap() {
+            Some(heap) => heap.dealloc(ptr, layout),
+            None => defmt::error!("muos-alloc: dealloc {:#x} without a heap", ptr as usize),
+        }
+    }
+}
+
+/// Usage of the running thread's heap, or `None` if it wasn't given one.
+pub fn heap_stats() -> Option<HeapStats> {
+    current_heap().map(|h| h.stats())
+}
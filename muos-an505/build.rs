@@ -0,0 +1,25 @@
+//! Put `memory.x` where cortex-m-rt's `link.x` finds it and have the linker
+//! emit the Secure gateway veneers.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+fn main() {
+    let out = PathBuf::from(std::env::var_os("OUT_DIR").unwrap());
+    println!("cargo:rustc-link-search={}", out.display());
+
+    let memory_x = include_bytes!("memory.x");
+    let mut f = File::create(out.join("memory.x")).unwrap();
+    f.write_all(memory_x).unwrap();
+    println!("cargo:rerun-if-changed=memory.x");
+
+    // one SG veneer per `nsc_exports!` function in .gnu.sgstubs, plus the
+    // import library a separately linked Non-secure image would need
+    println!("cargo:rustc-link-arg=--cmse-implib");
+    // lld wants the veneers at a fixed address; the top 4 kB of FLASH
+    println!("cargo:rustc-link-arg=--section-start=.gnu.sgstubs=0x101FF000");
+    println!("cargo:rustc-link-arg=--out-implib={}", out.join("muos_nsc.o").display());
+
+    println!("cargo:rerun-if-changed=build.rs");
+}
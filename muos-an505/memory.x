@@ -0,0 +1,91 @@
+/*
+ * Arm MPS2+ AN505 (Cortex-M33 + SSE-200), as QEMU's `mps2-an505` models it.
+ *
+ * The IDAU makes 0x1xxx_xxxx / 0x3xxx_xxxx the Secure and 0x0xxx_xxxx /
+ * 0x2xxx_xxxx the Non-secure alias of the same memory; the MPCs in front of
+ * the SSRAMs decide per block which alias actually gets through, see
+ * `mpc_set_nonsecure` in src/main.rs.
+ */
+MEMORY {
+  /* SSRAM1 (4 MB): lower half, Secure, for the kernel image */
+  FLASH : ORIGIN = 0x10000000, LENGTH = 2M
+  /* SSRAM1 upper half, Non-secure code; loaded through the Secure alias */
+  NS_FLASH : ORIGIN = 0x00200000, LENGTH = 2M
+  NS_FLASH_LOAD : ORIGIN = 0x10200000, LENGTH = 2M
+  /* SSRAM2 (2 MB), Secure */
+  RAM : ORIGIN = 0x38000000, LENGTH = 2M - 16K - 256 - 32
+  /* --- where the running thread's heap is, user read-only (MPU region 5) --- */
+  HEAP_INFO : ORIGIN = 0x381FBEE0, LENGTH = 32
+  /* --- per-thread heaps for muos-alloc (8 x 1 kB) --- */
+  THREAD_HEAPS : ORIGIN = 0x381FBF00, LENGTH = 8K
+  /* --- kernel boot info, survives everything but a power cycle --- */
+  PERSIST : ORIGIN = 0x381FDF00, LENGTH = 256
+  /* --- top 8 kB of SSRAM2 for RTOS thread stacks (8 x 1 kB) --- */
+  THREAD_STACKS : ORIGIN = 0x381FE000, LENGTH = 8K
+  /* SSRAM3 (2 MB), Non-secure data and stacks */
+  NS_RAM : ORIGIN = 0x28200000, LENGTH = 2M
+}
+
+SECTIONS {
+  .uninit.stacks (NOLOAD) : ALIGN(8)
+  {
+    *(.uninit.stacks .uninit.stacks.*);
+    . = ALIGN(8);
+  } > THREAD_STACKS
+
+  /* reset reason / boot counter, see muos-threads/src/bootinfo.rs */
+  .uninit.persist (NOLOAD) : ALIGN(4)
+  {
+    *(.uninit.persist .uninit.persist.*);
+  } > PERSIST
+
+  /* per-thread heaps and where the running thread's is, see
+     muos-threads/src/heap.rs */
+  .uninit.heaps (NOLOAD) : ALIGN(32)
+  {
+    *(.uninit.heaps .uninit.heaps.*);
+  } > THREAD_HEAPS
+
+  .uninit.heap_info (NOLOAD) : ALIGN(32)
+  {
+    *(.uninit.heap_info .uninit.heap_info.*);
+  } > HEAP_INFO
+
+  /* ### Non-secure world
+   *
+   * Vector table (VTOR_NS) and code of the Non-secure side, see src/ns.rs.
+   * Nothing in here may call into Secure code except through a veneer.
+   */
+  .ns_vectors : ALIGN(128)
+  {
+    __ns_vectors = .;
+    KEEP(*(.ns_vectors));
+  } > NS_FLASH AT > NS_FLASH_LOAD
+
+  .ns_text : ALIGN(4)
+  {
+    *(.ns_text .ns_text.*);
+    . = ALIGN(32);
+  } > NS_FLASH AT > NS_FLASH_LOAD
+
+  /* data shared with the Secure side, Non-secure thread stacks and the
+     Non-secure handler stack */
+  .uninit.ns (NOLOAD) : ALIGN(8)
+  {
+    *(.uninit.ns_data .uninit.ns_data.*);
+    . = ALIGN(8);
+    *(.uninit.ns_stacks .uninit.ns_stacks.*);
+    . = ALIGN(8);
+    . += 1K;
+    __ns_msp_top = .;
+  } > NS_RAM
+} INSERT AFTER .vector_table;
+
+/* the kernel finds the veneers cortex-m-rt's .gnu.sgstubs collects here */
+__sg_start = __veneer_base;
+__sg_end = __veneer_limit;
+
+/* The syscall gate's SG veneer, for the Non-secure SVCall in src/ns.rs.
+   Within this link `muos_ns_svc` resolves to the function behind the veneer,
+   so take the veneer's address: the gate is the kernel's only export. */
+ns_svc_gate = __veneer_base;
@@ -0,0 +1,90 @@
+//! CONTROL, PSPLIM and the FP state per thread, across context switches.
+//!
+//! The threads here share one priority with the Non-secure thread, so every
+//! `yield_now` or sleep hands the CPU to a thread with different settings:
+//! privileged or not, with or without an FP context, Secure or Non-secure.
+//! Each checks after every switch back that it got its own settings again
+//! and reports its bit only if all rounds did.
+
+use core::arch::asm;
+use cortex_m::register::control;
+use muos_syscall::{notify, sleep_ms, yield_now, NotifyAction, Sysno};
+use muos_threads::scheduler::with_scheduler;
+use muos_threads::thread::{CONTROL_FPCA, CONTROL_NPRIV, CONTROL_SPSEL};
+
+pub const PRIVILEGED: u32 = 1 << 8;
+pub const UNPRIVILEGED: u32 = 1 << 9;
+pub const FP_A: u32 = 1 << 10;
+pub const FP_B: u32 = 1 << 11;
+
+const ROUNDS: u32 = 20;
+
+// odd rounds block, so the switch back comes from SysTick rather than the SVC
+fn switch_away(round: u32) {
+    let _ = if round & 1 == 0 { yield_now() } else { sleep_ms(1) };
+}
+
+/// Privileged, on PSP, with PSPLIM at the bottom of its own stack.
+pub fn privileged_thread() {
+    let report_to = crate::report_to();
+    let (stack_base, _) = with_scheduler(|sched| sched.get_current_thread_stack());
+    for round in 0..ROUNDS {
+        let control = control::read().bits();
+        let psplim: u32;
+        unsafe { asm!("mrs {}, PSPLIM", out(reg) psplim, options(nomem, nostack, preserves_flags)) };
+        if control & (CONTROL_NPRIV | CONTROL_SPSEL) != CONTROL_SPSEL || psplim != stack_base as u32 {
+            return;
+        }
+        switch_away(round);
+    }
+    let _ = notify(report_to, PRIVILEGED, NotifyAction::SetBits);
+}
+
+/// Unprivileged, on PSP.  PSPLIM reads as zero here, so the privileged
+/// thread checks that one.
+pub fn unprivileged_thread() {
+    let report_to = crate::report_to();
+    for round in 0..ROUNDS {
+        if control::read().bits() & (CONTROL_NPRIV | CONTROL_SPSEL) != CONTROL_NPRIV | CONTROL_SPSEL {
+            return;
+        }
+        switch_away(round);
+    }
+    let _ = notify(report_to, UNPRIVILEGED, NotifyAction::SetBits);
+}
+
+pub fn fp_thread_a() {
+    fp_thread(1.5, FP_A);
+}
+
+pub fn fp_thread_b() {
+    fp_thread(-2.25, FP_B);
+}
+
+/// Keep `value` in s16 across a yield to the other FP thread, which puts
+/// its own there, and come back with FPCA still set.
+fn fp_thread(value: f32, bit: u32) {
+    let report_to = crate::report_to();
+    for _ in 0..ROUNDS {
+        let (kept, control): (u32, u32);
+        unsafe {
+            asm!(
+                "vmov  s16, {value}",
+                "svc   0",
+                "vmov  {kept}, s16",
+                "mrs   {control}, CONTROL",
+                value = in(reg) value.to_bits(),
+                kept = lateout(reg) kept,
+                control = lateout(reg) control,
+                in("r12") Sysno::YIELD_NOW as usize,
+                lateout("r0") _,
+                out("s16") _,
+                options(nostack),
+            );
+        }
+        if kept != value.to_bits() || control & CONTROL_FPCA == 0 {
+            return;
+        }
+    }
+    let _ = notify(report_to, bit, NotifyAction::SetBits);
+}
@@ -0,0 +1,38 @@
+//! Allocating from an unprivileged thread's private heap.
+//!
+//! Unprivileged code can't ask the MPU where its heap is mapped (`TT` comes
+//! back empty), so this is the thread `muos_alloc` has to rely on the heap the
+//! kernel publishes for.  Yielding between the allocations makes the kernel
+//! republish it for the other threads and then for this one again.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use muos_syscall::{notify, yield_now, NotifyAction};
+
+pub const HEAP: u32 = 1 << 18;
+
+const LEN: u32 = 16;
+
+pub fn heap_thread() {
+    let report_to = crate::report_to();
+    let Some(before) = muos_alloc::heap_stats() else { return };
+
+    let mut values: Vec<u32> = Vec::new();
+    if values.try_reserve(LEN as usize).is_err() {
+        return;
+    }
+    let _ = yield_now();
+    values.extend(0..LEN);
+    let boxed = Box::new(values.iter().sum::<u32>());
+    let _ = yield_now();
+
+    let Some(during) = muos_alloc::heap_stats() else { return };
+    let ok = *boxed == LEN * (LEN - 1) / 2 && during.allocs == before.allocs + 2 && during.used > before.used;
+    drop(boxed);
+    drop(values);
+
+    let Some(after) = muos_alloc::heap_stats() else { return };
+    if ok && after.frees == before.frees + 2 && after.used == before.used {
+        let _ = notify(report_to, HEAP, NotifyAction::SetBits);
+    }
+}
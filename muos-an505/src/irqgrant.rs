@@ -0,0 +1,24 @@
+//! Interrupt lines for unprivileged threads: only the ones a privileged
+//! thread granted.
+//!
+//! `test_thread` grants `GRANTED_IRQ` to `driver_thread` before letting it
+//! start.  Neither line is driven by anything, so the wait times out.
+
+use muos_syscall::{irq_attach, irq_wait, notify, NotifyAction, SyscallError};
+
+pub const GRANTED: u32 = 1 << 20;
+
+/// The dual timer's line.
+pub const GRANTED_IRQ: u16 = 5;
+/// TIMER0's line, never granted.
+const OTHER_IRQ: u16 = 3;
+
+pub fn driver_thread() {
+    let report_to = crate::report_to();
+    if irq_attach(OTHER_IRQ) != Err(SyscallError::NotPermitted) {
+        return;
+    }
+    if irq_attach(GRANTED_IRQ).is_ok() && irq_wait(GRANTED_IRQ, 20) == Err(SyscallError::TimedOut) {
+        let _ = notify(report_to, GRANTED, NotifyAction::SetBits);
+    }
+}
@@ -0,0 +1,206 @@
+//! muOS on the Arm MPS2+ AN505 (QEMU `mps2-an505`): the Secure kernel and a
+//! Non-secure thread in one image.
+//!
+//! ```text
+//! cd muos-an505 && cargo run --release
+//! ```
+//!
+//! runs it under QEMU (see .cargo/config.toml).  A Secure test thread
+//! collects the reports of the check threads and ends the run through
+//! semihosting, so the exit status says whether all of them passed.
+
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+mod context;
+mod heap;
+mod irqgrant;
+mod ns;
+mod preempt;
+mod select;
+mod semihosting;
+
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use cortex_m_rt::entry;
+use defmt_rtt as _;
+use cortex_m::interrupt;
+use muos_syscall::{irq_grant, notify_wait, wait_many, Handle, NotifyAction, WaitSpec, WAIT_FOREVER};
+use muos_threads::notify::notify;
+use muos_threads::scheduler::spawn_thread_with;
+use muos_threads::secure::{self, SauRegion};
+use muos_threads::thread::{ThreadAttrs, ThreadFn};
+use semihosting::Console;
+
+/// For `heap::heap_thread`, the only thread here with a heap.
+#[global_allocator]
+static ALLOC: muos_alloc::ThreadHeapAlloc = muos_alloc::ThreadHeapAlloc;
+
+/// QEMU runs the AN505 CPU at 25 MHz.
+const CPU_HZ: u32 = 25_000_000;
+
+// Non-secure memory, as laid out in memory.x
+const NS_CODE: SauRegion = SauRegion { base: 0x0020_0000, limit: 0x003F_FFFF, nsc: false };
+const NS_RAM: SauRegion = SauRegion { base: 0x2820_0000, limit: 0x283F_FFFF, nsc: false };
+
+// SSE-200 Secure privilege control: NSCCFG lets the SAU make parts of the
+// Secure code alias Non-secure callable
+const NSCCFG: *mut u32 = 0x5008_0014 as *mut u32;
+const NSCCFG_CODENSC: u32 = 1 << 0;
+
+// Memory protection controllers in front of SSRAM1 and SSRAM3
+const MPC_SSRAM1: usize = 0x5800_7000;
+const MPC_SSRAM3: usize = 0x5800_9000;
+const MPC_BLK_CFG: usize = 0x14;
+const MPC_BLK_IDX: usize = 0x18;
+const MPC_BLK_LUT: usize = 0x1C;
+
+const VTOR_NS: *mut u32 = 0xE002_ED08 as *mut u32;
+
+/// Checks and the bits their threads report to `test_thread` on success.
+const CHECKS: [(&str, u32); 10] = [
+    ("non-secure thread", ns::ALL_PASSED),
+    ("privileged context", context::PRIVILEGED),
+    ("unprivileged context", context::UNPRIVILEGED),
+    ("FP context A", context::FP_A),
+    ("FP context B", context::FP_B),
+    ("wake before PendSV", preempt::WOKEN_FIRST),
+    ("masked after irq_wait timeout", preempt::MASKED_AFTER_TIMEOUT),
+    ("wait_many", select::WAIT_MANY),
+    ("unprivileged heap", heap::HEAP),
+    ("granted interrupt line", irqgrant::GRANTED),
+];
+const REPORT_TIMEOUT_MS: u32 = 2000;
+
+extern "C" {
+    static __ns_vectors: u32;
+    static __ns_msp_top: u32;
+}
+
+#[entry]
+fn main() -> ! {
+    let mut core = cortex_m::peripheral::Peripherals::take().unwrap();
+
+    // src/ns.rs calls the gate through the first veneer, see memory.x
+    assert!(secure::NSC_EXPORTS == ["muos_ns_svc"]);
+    muos_threads::init(CPU_HZ, &mut core);
+    unsafe { init_security() };
+
+    let report_to = spawn_thread_with(test_thread as ThreadFn, ThreadAttrs { prio: 1, privileged: true, ..Default::default() });
+    unsafe { ns::REPORT_TO = report_to.raw() };
+    spawn_thread_with(ns::ns_thread as ThreadFn, ThreadAttrs { nonsecure: true, ..Default::default() });
+
+    muos_threads::boot();
+    panic!("still in main after boot");
+}
+
+/// Open the Non-secure memory in memory.x to the Non-secure world and point
+/// it at its vector table.
+unsafe fn init_security() {
+    mpc_set_nonsecure(MPC_SSRAM1, NS_CODE.base as usize, (NS_CODE.limit - NS_CODE.base + 1) as usize);
+    mpc_set_nonsecure(MPC_SSRAM3, 0, (NS_RAM.limit - NS_RAM.base + 1) as usize);
+    NSCCFG.write_volatile(NSCCFG.read_volatile() | NSCCFG_CODENSC);
+
+    secure::init(&[NS_CODE, NS_RAM]);
+
+    VTOR_NS.write_volatile(&__ns_vectors as *const u32 as u32);
+    let msp_ns = &__ns_msp_top as *const u32 as u32;
+    core::arch::asm!("msr MSP_NS, {}", in(reg) msp_ns, options(nomem, nostack, preserves_flags));
+    cortex_m::asm::dsb();
+    cortex_m::asm::isb();
+}
+
+/// Mark `[offset, offset + len)` of the memory behind `mpc` Non-secure.
+unsafe fn mpc_set_nonsecure(mpc: usize, offset: usize, len: usize) {
+    let reg = |off: usize| (mpc + off) as *mut u32;
+    let block_size = 1usize << (reg(MPC_BLK_CFG).read_volatile() + 5);
+    for block in offset / block_size..(offset + len) / block_size {
+        // one LUT word per 32 blocks, a set bit means Non-secure
+        reg(MPC_BLK_IDX).write_volatile((block / 32) as u32);
+        let lut = reg(MPC_BLK_LUT).read_volatile();
+        reg(MPC_BLK_LUT).write_volatile(lut | 1 << (block % 32));
+    }
+}
+
+/// Spawn a Secure check thread, with `report_to` waiting for it as its first
+/// notification: unprivileged threads can't read it from a static.
+fn spawn_check(thread_fn: ThreadFn, attrs: ThreadAttrs, report_to: Handle) -> Handle {
+    let thread = spawn_thread_with(thread_fn, attrs);
+    notify(thread, report_to.raw(), NotifyAction::Overwrite).unwrap();
+    thread
+}
+
+/// The thread to report to, for a thread started by `spawn_check`.
+fn report_to() -> Handle {
+    Handle::from_raw(notify_wait(0, u32::MAX, WAIT_FOREVER).unwrap_or(0))
+}
+
+/// Reports until all of `want` are in, or they stop coming.
+fn collect(want: u32) -> u32 {
+    let mut passed = 0;
+    while passed & want != want {
+        match notify_wait(0, u32::MAX, REPORT_TIMEOUT_MS) {
+            Ok(bits) => passed |= bits,
+            Err(_) => break,
+        }
+    }
+    passed
+}
+
+/// Wait for `threads` to exit and free their slots.
+fn join(threads: &[Handle]) {
+    for &thread in threads {
+        let _ = wait_many(&[WaitSpec::thread_exit(thread)], REPORT_TIMEOUT_MS);
+    }
+}
+
+/// Runs the checks in three rounds: of the kernel's eight thread slots the
+/// worker, idle and Non-secure threads and this one hold four throughout.
+fn test_thread() {
+    let me = Handle::from_raw(unsafe { ns::REPORT_TO });
+
+    let context = [
+        spawn_check(context::privileged_thread, ThreadAttrs { privileged: true, ..Default::default() }, me),
+        spawn_check(context::unprivileged_thread, ThreadAttrs::default(), me),
+        spawn_check(context::fp_thread_a, ThreadAttrs { fp: true, ..Default::default() }, me),
+        spawn_check(context::fp_thread_b, ThreadAttrs { fp: true, ..Default::default() }, me),
+    ];
+    let mut passed = collect(ns::ALL_PASSED | context::PRIVILEGED | context::UNPRIVILEGED | context::FP_A | context::FP_B);
+    join(&context);
+
+    // nothing may run before all of these are in: `low_thread` has to be
+    // ready by the time `high_thread` starts
+    let timing = interrupt::free(|_| {
+        let low = spawn_thread_with(preempt::low_thread as ThreadFn, ThreadAttrs { prio: preempt::LOW_PRIO, privileged: true, ..Default::default() });
+        let high = spawn_check(preempt::high_thread, ThreadAttrs { prio: preempt::HIGH_PRIO, privileged: true, ..Default::default() }, me);
+        let waiter = spawn_check(select::waiter_thread, ThreadAttrs { prio: select::WAITER_PRIO, privileged: true, ..Default::default() }, me);
+        let helper = spawn_check(select::helper_thread, ThreadAttrs { prio: select::HELPER_PRIO, privileged: true, ..Default::default() }, waiter);
+        [low, high, waiter, helper]
+    });
+    passed |= collect(preempt::WOKEN_FIRST | preempt::MASKED_AFTER_TIMEOUT | select::WAIT_MANY);
+    join(&timing);
+
+    spawn_check(heap::heap_thread, ThreadAttrs { heap: true, ..Default::default() }, me);
+    // granted before it gets its report_to and starts
+    let driver = spawn_thread_with(irqgrant::driver_thread as ThreadFn, ThreadAttrs::default());
+    if irq_grant(irqgrant::GRANTED_IRQ, driver).is_ok() {
+        notify(driver, me.raw(), NotifyAction::Overwrite).unwrap();
+    }
+    passed |= collect(heap::HEAP | irqgrant::GRANTED);
+
+    let all = CHECKS.iter().fold(0, |bits, (_, check)| bits | check);
+    for (name, check) in CHECKS {
+        let ok = passed & check == check;
+        let _ = writeln!(Console, "{}: {}", name, if ok { "ok" } else { "FAILED" });
+    }
+    semihosting::exit(passed & all == all);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cortex_m::interrupt::disable();
+    let _ = writeln!(Console, "panic: {}", info);
+    semihosting::exit(false)
+}
@@ -0,0 +1,99 @@
+//! The Non-secure side: a vector table whose SVCall forwards into the
+//! kernel's `muos_ns_svc` gate, and one Non-secure thread.
+//!
+//! Everything here is linked into Non-secure memory (`.ns_vectors`,
+//! `.ns_text`, see memory.x) and must not call into Secure code except
+//! through the veneer: Non-secure code branching anywhere else in the Secure
+//! image takes a SecureFault.  So the thread sticks to the inline raw syscall
+//! stubs and plain loads and stores.
+
+use core::arch::global_asm;
+use muos_syscall::asm::{syscall1, syscall3, syscall4};
+use muos_syscall::{NotifyAction, Sysno, SyscallError};
+
+/// Notification bits the thread reports to the Secure test thread.
+pub const SECURE_PTR_REFUSED: u32 = 1 << 0;
+pub const OWN_PTR_ACCEPTED: u32 = 1 << 1;
+pub const SLEPT: u32 = 1 << 2;
+pub const ALL_PASSED: u32 = SECURE_PTR_REFUSED | OWN_PTR_ACCEPTED | SLEPT;
+
+const FAULT: usize = (SyscallError::Fault as usize).wrapping_neg();
+const WOULD_BLOCK: usize = (SyscallError::WouldBlock as usize).wrapping_neg();
+
+/// Handle of the thread to report to, set by the Secure side before boot.
+#[link_section = ".uninit.ns_data"]
+pub static mut REPORT_TO: u32 = 0;
+
+/// Secure RAM (plain .bss) the thread tries to get the kernel to write to.
+static mut SECURE_PROBE: u32 = 0;
+
+#[link_section = ".ns_text"]
+pub fn ns_thread() {
+    let mut passed = 0;
+
+    // the kernel refuses to write a notification word into Secure memory...
+    let probe = core::ptr::addr_of!(SECURE_PROBE) as usize;
+    if syscall4(Sysno::NOTIFY_WAIT as usize, 0, 0, probe, 0) == FAULT {
+        passed |= SECURE_PTR_REFUSED;
+    }
+    // ...but takes one on our own stack: zeroed, then nothing pending
+    let mut word = u32::MAX;
+    let raw = syscall4(Sysno::NOTIFY_WAIT as usize, 0, 0, &mut word as *mut u32 as usize, 0);
+    if raw == WOULD_BLOCK && word == 0 {
+        passed |= OWN_PTR_ACCEPTED;
+    }
+    // blocking and being switched back in on the NS bank
+    if syscall1(Sysno::SLEEP_MS as usize, 20) == 0 {
+        passed |= SLEPT;
+    }
+
+    let report_to = unsafe { REPORT_TO } as usize;
+    syscall3(Sysno::NOTIFY as usize, report_to, passed as usize, NotifyAction::SetBits as usize);
+    loop {
+        syscall1(Sysno::SLEEP_MS as usize, 1000);
+    }
+}
+
+global_asm!(
+    ".section .ns_vectors, \"a\", %progbits",
+    ".global ns_vectors",
+    "ns_vectors:",
+    ".word __ns_msp_top",
+    ".word ns_fault",   // Reset, never taken: the Secure side boots
+    ".word ns_fault",   // NMI
+    ".word ns_fault",   // HardFault
+    ".word ns_fault",   // MemManage
+    ".word ns_fault",   // BusFault
+    ".word ns_fault",   // UsageFault
+    ".word 0",          // SecureFault, Secure only
+    ".word 0",
+    ".word 0",
+    ".word 0",
+    ".word ns_svcall",  // SVCall
+    ".word ns_fault",   // DebugMonitor
+    ".word 0",
+    ".word ns_fault",   // PendSV
+    ".word ns_fault",   // SysTick
+
+    ".section .ns_text, \"ax\", %progbits",
+    ".thumb_func",
+    "ns_fault:",
+    "    b     ns_fault",
+
+    // muos-syscall's `nonsecure` SVCall, as a separately linked Non-secure
+    // image would have it, except that linked together with the Secure side
+    // `bl muos_ns_svc` would skip the veneer: `ns_svc_gate` is the veneer
+    ".thumb_func",
+    "ns_svcall:",
+    "    tst   lr, #4",
+    "    ite   eq",
+    "    mrseq r0, MSP",
+    "    mrsne r0, PSP",
+    "    mov   r1, r4",
+    "    push  {{r4, lr}}",
+    "    ldr   r2, =ns_svc_gate",
+    "    orr   r2, r2, #1",
+    "    blx   r2",
+    "    pop   {{r4, pc}}",
+    ".ltorg",
+);
@@ -0,0 +1,59 @@
+//! A thread woken by an interrupt between blocking and the switch away.
+//!
+//! `high_thread` pends an interrupt line it owns and then `irq_wait`s on it.
+//! The kernel blocks the thread, unmasks the line and pends PendSV; the line
+//! outranks PendSV, so its handler runs first and wakes the thread while it
+//! is still the current one.  PendSV then has to let it carry on, not hand
+//! the CPU to the lower priority `low_thread` that has been ready all along.
+//!
+//! Then it waits on the line again, with nothing to fire it, and checks that
+//! the timeout left the line masked.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use cortex_m::interrupt::InterruptNumber;
+use cortex_m::peripheral::NVIC;
+use muos_syscall::{irq_attach, irq_wait, notify, NotifyAction, SyscallError};
+
+pub const WOKEN_FIRST: u32 = 1 << 16;
+pub const MASKED_AFTER_TIMEOUT: u32 = 1 << 19;
+
+pub const HIGH_PRIO: u32 = 3;
+pub const LOW_PRIO: u32 = 2;
+
+/// TIMER1's line, which nothing else drives: the timer is never started.
+const TEST_IRQ: u16 = 4;
+/// Below SVCall (0), above PendSV (0xFF).
+const TEST_IRQ_PRIO: u8 = 0x80;
+
+#[derive(Copy, Clone)]
+struct TestIrq;
+
+unsafe impl InterruptNumber for TestIrq {
+    fn number(self) -> u16 {
+        TEST_IRQ
+    }
+}
+
+static LOW_RAN: AtomicBool = AtomicBool::new(false);
+
+pub fn high_thread() {
+    let report_to = crate::report_to();
+    if irq_attach(TEST_IRQ).is_err() {
+        return;
+    }
+    unsafe { (*NVIC::PTR).ipr[TEST_IRQ as usize].write(TEST_IRQ_PRIO) };
+    // latched while masked; fires as soon as the SVC below returns
+    NVIC::pend(TestIrq);
+    let woken = irq_wait(TEST_IRQ, 1000);
+    if woken.is_ok() && !LOW_RAN.load(Ordering::SeqCst) {
+        let _ = notify(report_to, WOKEN_FIRST, NotifyAction::SetBits);
+    }
+
+    if irq_wait(TEST_IRQ, 20) == Err(SyscallError::TimedOut) && !NVIC::is_enabled(TestIrq) {
+        let _ = notify(report_to, MASKED_AFTER_TIMEOUT, NotifyAction::SetBits);
+    }
+}
+
+pub fn low_thread() {
+    LOW_RAN.store(true, Ordering::SeqCst);
+}
@@ -0,0 +1,51 @@
+//! `wait_many` on a latch, a stream and a notification, each of which a
+//! lower priority helper makes fire in turn while the waiter is blocked.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use muos_syscall::{latch_count_down, latch_create, notify, stream_create, stream_read, stream_write,
+                   wait_many, Handle, NotifyAction, WaitSpec, WAIT_FOREVER};
+
+pub const WAIT_MANY: u32 = 1 << 17;
+
+pub const WAITER_PRIO: u32 = 2;
+pub const HELPER_PRIO: u32 = 1;
+
+const TRIGGER: usize = 4;
+const NOTIFY_VALUE: u32 = 0x5A;
+const TIMEOUT_MS: u32 = 1000;
+
+static LATCH: AtomicU32 = AtomicU32::new(0);
+static STREAM: AtomicU32 = AtomicU32::new(0);
+
+pub fn waiter_thread() {
+    let report_to = crate::report_to();
+    let (Ok(latch), Ok(stream)) = (latch_create(1), stream_create(16, TRIGGER as u32)) else { return };
+    LATCH.store(latch.raw(), Ordering::SeqCst);
+    STREAM.store(stream.raw(), Ordering::SeqCst);
+
+    let mut word = 0;
+    let specs = [WaitSpec::latch(latch), WaitSpec::stream_readable(stream), WaitSpec::notify(&mut word, u32::MAX)];
+    // the helper's write wakes us without reading anything...
+    if wait_many(&specs, TIMEOUT_MS) != Ok(1) || stream_read(stream, &mut [0; 16], 0) != Ok(TRIGGER) {
+        return;
+    }
+    // ...then it opens the latch...
+    if wait_many(&specs, TIMEOUT_MS) != Ok(0) {
+        return;
+    }
+    // ...and notifies us, with the latch left out as it stays open
+    if wait_many(&specs[1..], TIMEOUT_MS) != Ok(1) || unsafe { core::ptr::read_volatile(&word) } != NOTIFY_VALUE {
+        return;
+    }
+    let _ = notify(report_to, WAIT_MANY, NotifyAction::SetBits);
+}
+
+/// Started with the waiter as the thread to "report" to.
+pub fn helper_thread() {
+    let waiter = crate::report_to();
+    let latch = Handle::from_raw(LATCH.load(Ordering::SeqCst));
+    let stream = Handle::from_raw(STREAM.load(Ordering::SeqCst));
+    let _ = stream_write(stream, &[0; TRIGGER], WAIT_FOREVER);
+    let _ = latch_count_down(latch);
+    let _ = notify(waiter, NOTIFY_VALUE, NotifyAction::SetBits);
+}
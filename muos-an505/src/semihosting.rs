@@ -0,0 +1,38 @@
+//! Just enough Arm semihosting to report results: QEMU has to run with
+//! `-semihosting-config enable=on`.  Privileged callers only.
+
+use core::arch::asm;
+use core::fmt;
+
+const SYS_WRITEC: usize = 0x03;
+const SYS_EXIT: usize = 0x18;
+
+const ADP_STOPPED_APPLICATION_EXIT: usize = 0x2_0026;
+const ADP_STOPPED_RUNTIME_ERROR_UNKNOWN: usize = 0x2_0023;
+
+unsafe fn call(op: usize, arg: usize) -> usize {
+    let ret;
+    asm!("bkpt 0xAB", inlateout("r0") op => ret, in("r1") arg, options(nostack));
+    ret
+}
+
+/// Stop the emulator; QEMU exits with 0 for `success`, 1 otherwise.
+pub fn exit(success: bool) -> ! {
+    let reason = if success { ADP_STOPPED_APPLICATION_EXIT } else { ADP_STOPPED_RUNTIME_ERROR_UNKNOWN };
+    unsafe { call(SYS_EXIT, reason) };
+    loop {
+        cortex_m::asm::bkpt();
+    }
+}
+
+/// The host's console, for `write!`.
+pub struct Console;
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.bytes() {
+            unsafe { call(SYS_WRITEC, &c as *const u8 as usize) };
+        }
+        Ok(())
+    }
+}
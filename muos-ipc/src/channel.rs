@@ -0,0 +1,233 @@
+use core::cell::UnsafeCell;
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use crate::Kernel;
+
+/// A bounded queue of up to `N` values from one sender to one receiver.
+///
+/// `head` and `tail` count reads and writes modulo `2 * N`, so a full ring
+/// and an empty one look different; they double as the futex words a
+/// blocked side sleeps on.  A side about to sleep raises its flag first, so
+/// the other only makes the wake syscall when it has to.
+pub struct Channel<T: Copy, const N: usize, K: Kernel> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    head: AtomicU32, // next read
+    tail: AtomicU32, // next write
+    rx_sleeping: AtomicBool,
+    tx_sleeping: AtomicBool,
+    sender_taken: AtomicBool,
+    receiver_taken: AtomicBool,
+    _kernel: PhantomData<K>,
+}
+
+unsafe impl<T: Copy + Send, const N: usize, K: Kernel> Sync for Channel<T, N, K> {}
+
+impl<T: Copy, const N: usize, K: Kernel> Channel<T, N, K> {
+    pub const fn new() -> Self {
+        assert!(N > 0 && N <= u32::MAX as usize / 2);
+        Channel {
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: AtomicU32::new(0),
+            tail: AtomicU32::new(0),
+            rx_sleeping: AtomicBool::new(false),
+            tx_sleeping: AtomicBool::new(false),
+            sender_taken: AtomicBool::new(false),
+            receiver_taken: AtomicBool::new(false),
+            _kernel: PhantomData,
+        }
+    }
+
+    /// The sending end; `None` once it's been handed out.
+    pub fn sender(&self) -> Option<Sender<'_, T, N, K>> {
+        (!self.sender_taken.swap(true, Ordering::AcqRel)).then_some(Sender { chan: self })
+    }
+
+    /// The receiving end; `None` once it's been handed out.
+    pub fn receiver(&self) -> Option<Receiver<'_, T, N, K>> {
+        (!self.receiver_taken.swap(true, Ordering::AcqRel)).then_some(Receiver { chan: self })
+    }
+
+    /// Values queued right now.
+    pub fn len(&self) -> usize {
+        Self::count(self.head.load(Ordering::Acquire), self.tail.load(Ordering::Acquire))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn count(head: u32, tail: u32) -> usize {
+        (tail as usize + 2 * N - head as usize) % (2 * N)
+    }
+
+    fn next(index: u32) -> u32 {
+        (index + 1) % (2 * N as u32)
+    }
+
+    fn slot(&self, index: u32) -> *mut MaybeUninit<T> {
+        self.slots[index as usize % N].get()
+    }
+
+    fn try_send(&self, value: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        if Self::count(self.head.load(Ordering::SeqCst), tail) == N {
+            return Err(value);
+        }
+        // the slot is ours: the receiver is done with it and won't look
+        // before `tail` moves past it
+        unsafe { (*self.slot(tail)).write(value) };
+        self.tail.store(Self::next(tail), Ordering::SeqCst);
+        if self.rx_sleeping.swap(false, Ordering::SeqCst) {
+            K::futex_wake(&self.tail, 1);
+        }
+        Ok(())
+    }
+
+    fn send(&self, mut value: T) {
+        loop {
+            value = match self.try_send(value) {
+                Ok(()) => return,
+                Err(v) => v,
+            };
+            let head = self.head.load(Ordering::SeqCst);
+            self.tx_sleeping.store(true, Ordering::SeqCst);
+            // re-check after raising the flag, or a receive in between would
+            // go unnoticed
+            if Self::count(self.head.load(Ordering::SeqCst), self.tail.load(Ordering::Relaxed)) == N {
+                K::futex_wait(&self.head, head);
+            }
+        }
+    }
+
+    fn try_recv(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        if self.tail.load(Ordering::SeqCst) == head {
+            return None;
+        }
+        let value = unsafe { (*self.slot(head)).assume_init_read() };
+        self.head.store(Self::next(head), Ordering::SeqCst);
+        if self.tx_sleeping.swap(false, Ordering::SeqCst) {
+            K::futex_wake(&self.head, 1);
+        }
+        Some(value)
+    }
+
+    fn recv(&self) -> T {
+        loop {
+            if let Some(value) = self.try_recv() {
+                return value;
+            }
+            let tail = self.tail.load(Ordering::SeqCst);
+            self.rx_sleeping.store(true, Ordering::SeqCst);
+            if self.tail.load(Ordering::SeqCst) == self.head.load(Ordering::Relaxed) {
+                K::futex_wait(&self.tail, tail);
+            }
+        }
+    }
+}
+
+impl<T: Copy, const N: usize, K: Kernel> Default for Channel<T, N, K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Sending end of a `Channel`.
+pub struct Sender<'a, T: Copy, const N: usize, K: Kernel> {
+    chan: &'a Channel<T, N, K>,
+}
+
+impl<T: Copy, const N: usize, K: Kernel> Sender<'_, T, N, K> {
+    /// Queue `value`, sleeping while the channel is full.
+    pub fn send(&self, value: T) {
+        self.chan.send(value)
+    }
+
+    /// Queue `value` unless the channel is full, in which case it's handed back.
+    pub fn try_send(&self, value: T) -> Result<(), T> {
+        self.chan.try_send(value)
+    }
+}
+
+/// Receiving end of a `Channel`.
+pub struct Receiver<'a, T: Copy, const N: usize, K: Kernel> {
+    chan: &'a Channel<T, N, K>,
+}
+
+impl<T: Copy, const N: usize, K: Kernel> Receiver<'_, T, N, K> {
+    /// Take the oldest value, sleeping while there's none.
+    pub fn recv(&self) -> T {
+        self.chan.recv()
+    }
+
+    pub fn try_recv(&self) -> Option<T> {
+        self.chan.try_recv()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::Sim;
+    use std::thread;
+
+    #[test]
+    fn ends_are_handed_out_once() {
+        let chan: Channel<u8, 2, Sim> = Channel::new();
+        assert!(chan.sender().is_some());
+        assert!(chan.sender().is_none());
+        assert!(chan.receiver().is_some());
+        assert!(chan.receiver().is_none());
+    }
+
+    #[test]
+    fn try_send_stops_at_capacity() {
+        let chan: Channel<u32, 2, Sim> = Channel::new();
+        let (tx, rx) = (chan.sender().unwrap(), chan.receiver().unwrap());
+        assert_eq!(rx.try_recv(), None);
+        assert_eq!(tx.try_send(1), Ok(()));
+        assert_eq!(tx.try_send(2), Ok(()));
+        assert_eq!(tx.try_send(3), Err(3));
+        assert_eq!(chan.len(), 2);
+        assert_eq!(rx.try_recv(), Some(1));
+        assert_eq!(tx.try_send(3), Ok(()));
+        assert_eq!(rx.try_recv(), Some(2));
+        assert_eq!(rx.try_recv(), Some(3));
+        assert!(chan.is_empty());
+    }
+
+    #[test]
+    fn blocking_send_and_recv_keep_order() {
+        static CHAN: Channel<(u32, u64), 3, Sim> = Channel::new();
+        let producer = thread::spawn(|| {
+            let tx = CHAN.sender().unwrap();
+            for i in 0..10_000u32 {
+                tx.send((i, i as u64 * 3));
+            }
+        });
+        let rx = CHAN.receiver().unwrap();
+        for i in 0..10_000u32 {
+            assert_eq!(rx.recv(), (i, i as u64 * 3));
+        }
+        producer.join().unwrap();
+        assert_eq!(rx.try_recv(), None);
+    }
+
+    #[test]
+    fn indices_wrap_for_any_capacity() {
+        let chan: Channel<u32, 3, Sim> = Channel::new();
+        let (tx, rx) = (chan.sender().unwrap(), chan.receiver().unwrap());
+        for round in 0..5 {
+            for i in 0..3 {
+                tx.try_send(round * 3 + i).unwrap();
+            }
+            assert_eq!(tx.try_send(99), Err(99));
+            assert_eq!(chan.len(), 3);
+            for i in 0..3 {
+                assert_eq!(rx.try_recv(), Some(round * 3 + i));
+            }
+            assert_eq!(rx.try_recv(), None);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Typed messaging on top of the kernel's futexes and IPC endpoints.
+//!
+//! - `Channel<T, N, K>`: a bounded single-producer, single-consumer queue of
+//!   `Copy` values.  The ring lives in plain memory and only the blocking
+//!   goes through the kernel, so like the `muos-sync` types it has to sit
+//!   where both threads' MPU regions reach.
+//! - `rpc!`: turns a trait into a client stub and a server loop that talk
+//!   over an IPC endpoint, arguments and results encoded with postcard.
+//!   The kernel copies the bytes, so client and server share no memory.
+//!
+//! Everything is generic over a `Kernel`, which is `Svc` (the real syscalls)
+//! on the target.  The unit tests run on the host against a simulated kernel:
+//!
+//! ```text
+//! cargo test -p muos-ipc --target x86_64-unknown-linux-gnu
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+mod channel;
+pub mod rpc;
+#[cfg(test)]
+mod sim;
+
+pub use crate::channel::{Channel, Receiver, Sender};
+pub use crate::rpc::Fault;
+
+use core::fmt::Debug;
+use core::sync::atomic::AtomicU32;
+
+/// Largest encoded message, request or reply; the kernel's `MAX_IPC_MSG`.
+pub const MAX_MSG: usize = 128;
+
+#[cfg(target_os = "none")]
+const _: () = assert!(MAX_MSG == muos_syscall::MAX_IPC_MSG);
+
+/// The kernel services the channels and RPC layer need.
+pub trait Kernel {
+    /// Names an endpoint, or a client thread to reply to.
+    type Handle: Copy;
+    type Error: Copy + Debug;
+
+    /// Sleep while `word` reads `expected`; may return spuriously.
+    fn futex_wait(word: &AtomicU32, expected: u32);
+    /// Wake up to `count` threads sleeping on `word`.
+    fn futex_wake(word: &AtomicU32, count: u32);
+
+    /// Send `msg` on `endpoint` and block for the reply; returns its length.
+    fn call(endpoint: Self::Handle, msg: &[u8], reply: &mut [u8]) -> Result<usize, Self::Error>;
+    /// Block for a call on `endpoint`; returns the client and message length.
+    fn recv(endpoint: Self::Handle, buf: &mut [u8]) -> Result<(Self::Handle, usize), Self::Error>;
+    /// Answer the call `client` is blocked in.
+    fn reply(client: Self::Handle, msg: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Why a channel or RPC operation failed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Error<E> {
+    /// The kernel refused the IPC.
+    Kernel(E),
+    /// The request doesn't fit in `MAX_MSG` bytes.
+    Encode,
+    /// The reply didn't decode as the expected type.
+    Decode,
+    /// The server couldn't handle the request.
+    Remote(Fault),
+}
+
+/// The kernel proper, through the syscall stubs.
+#[cfg(target_os = "none")]
+pub struct Svc;
+
+#[cfg(target_os = "none")]
+impl Kernel for Svc {
+    type Handle = muos_syscall::Handle;
+    type Error = muos_syscall::SyscallError;
+
+    fn futex_wait(word: &AtomicU32, expected: u32) {
+        let addr = muos_syscall::UserPtr::new(word.as_ptr() as usize);
+        match muos_syscall::futex_wait(addr, expected, muos_syscall::WAIT_FOREVER) {
+            Ok(()) | Err(muos_syscall::SyscallError::WouldBlock) => {}
+            Err(e) => panic!("futex_wait: {:?}", e),
+        }
+    }
+
+    fn futex_wake(word: &AtomicU32, count: u32) {
+        let addr = muos_syscall::UserPtr::new(word.as_ptr() as usize);
+        if let Err(e) = muos_syscall::futex_wake(addr, count) {
+            panic!("futex_wake: {:?}", e);
+        }
+    }
+
+    fn call(endpoint: Self::Handle, msg: &[u8], reply: &mut [u8]) -> Result<usize, Self::Error> {
+        muos_syscall::ipc_call(endpoint, msg, reply, muos_syscall::WAIT_FOREVER)
+    }
+
+    fn recv(endpoint: Self::Handle, buf: &mut [u8]) -> Result<(Self::Handle, usize), Self::Error> {
+        muos_syscall::ipc_recv(endpoint, buf, muos_syscall::WAIT_FOREVER)
+    }
+
+    fn reply(client: Self::Handle, msg: &[u8]) -> Result<(), Self::Error> {
+        muos_syscall::ipc_reply(client, msg)
+    }
+}
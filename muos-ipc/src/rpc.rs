@@ -0,0 +1,291 @@
+//! Remote procedure calls over IPC endpoints.
+//!
+//! ```ignore
+//! muos_ipc::rpc! {
+//!     /// Key-value store.
+//!     pub trait Store => StoreClient {
+//!         fn get(&mut self, key: u32) -> Option<u32>;
+//!         fn set(&mut self, key: u32, value: u32);
+//!     }
+//! }
+//!
+//! // server thread
+//! MyStore::default().serve::<Svc>(endpoint);
+//! // client thread
+//! let store = StoreClient::<Svc>::new(endpoint);
+//! store.set(1, 42)?;
+//! ```
+//!
+//! The server implements the trait and runs `serve`; the client struct has
+//! the same methods, minus `self`'s mutability and returning `Result`.
+//! Arguments and results are owned `serde` types; a request is the method's
+//! id followed by the argument tuple, a reply the postcard-encoded
+//! `Result<T, Fault>`, each at most `MAX_MSG` bytes.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use crate::{Error, Kernel, MAX_MSG};
+
+/// What went wrong on the server's side of a call.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Fault {
+    /// The request didn't decode, e.g. client and server disagree on the
+    /// trait.
+    BadRequest,
+    /// No method with the requested id.
+    UnknownMethod,
+    /// The result doesn't fit in `MAX_MSG` bytes.
+    ReplyTooLarge,
+}
+
+/// Method id: FNV-1a of the method's name.
+#[doc(hidden)]
+pub const fn method_id(name: &str) -> u32 {
+    let bytes = name.as_bytes();
+    let mut hash: u32 = 0x811c_9dc5;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash ^= bytes[i] as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+        i += 1;
+    }
+    hash
+}
+
+/// Client side of one call.
+#[doc(hidden)]
+pub fn call<K: Kernel, A: Serialize, R: DeserializeOwned>(
+    endpoint: K::Handle,
+    method: u32,
+    args: &A,
+) -> Result<R, Error<K::Error>> {
+    let mut msg = [0u8; MAX_MSG];
+    let mut reply = [0u8; MAX_MSG];
+    let len = postcard::to_slice(&(method, args), &mut msg).map_err(|_| Error::Encode)?.len();
+    let n = K::call(endpoint, &msg[..len], &mut reply).map_err(Error::Kernel)?;
+    let result: Result<R, Fault> = postcard::from_bytes(&reply[..n]).map_err(|_| Error::Decode)?;
+    result.map_err(Error::Remote)
+}
+
+/// Server loop: answer each call on `endpoint` with what `dispatch` writes
+/// into the reply buffer.  Only returns if receiving fails.
+#[doc(hidden)]
+pub fn serve<K: Kernel>(
+    endpoint: K::Handle,
+    mut dispatch: impl FnMut(u32, &[u8], &mut [u8]) -> Result<usize, Fault>,
+) -> Error<K::Error> {
+    let mut msg = [0u8; MAX_MSG];
+    let mut reply = [0u8; MAX_MSG];
+    loop {
+        let (client, len) = match K::recv(endpoint, &mut msg) {
+            Ok(r) => r,
+            Err(e) => return Error::Kernel(e),
+        };
+        let result = match postcard::take_from_bytes::<u32>(&msg[..len]) {
+            Ok((method, args)) => dispatch(method, args, &mut reply),
+            Err(_) => Err(Fault::BadRequest),
+        };
+        let n = match result {
+            Ok(n) => n,
+            Err(fault) => encode_reply(&Err::<(), _>(fault), &mut reply).unwrap_or(0),
+        };
+        // the client may be gone by now; that's its problem
+        let _ = K::reply(client, &reply[..n]);
+    }
+}
+
+#[doc(hidden)]
+pub fn decode_args<A: DeserializeOwned>(args: &[u8]) -> Result<A, Fault> {
+    postcard::from_bytes(args).map_err(|_| Fault::BadRequest)
+}
+
+#[doc(hidden)]
+pub fn encode_reply<R: Serialize>(result: &Result<R, Fault>, buf: &mut [u8]) -> Result<usize, Fault> {
+    postcard::to_slice(result, buf).map(|b| b.len()).map_err(|_| Fault::ReplyTooLarge)
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __rpc_ret {
+    () => { () };
+    ($ret:ty) => { $ret };
+}
+
+/// Declare an RPC interface: `trait Name => ClientName { fn ...; }`.
+/// Every method takes `&mut self`; see the module docs.
+#[macro_export]
+macro_rules! rpc {
+    (
+        $(#[$meta:meta])*
+        $vis:vis trait $name:ident => $client:ident {
+            $(
+                $(#[$mmeta:meta])*
+                fn $method:ident(&mut self $(, $arg:ident: $ty:ty)* $(,)?) $(-> $ret:ty)?;
+            )*
+        }
+    ) => {
+        $(#[$meta])*
+        $vis trait $name {
+            $(
+                $(#[$mmeta])*
+                fn $method(&mut self $(, $arg: $ty)*) -> $crate::__rpc_ret!($($ret)?);
+            )*
+
+            /// Answer calls on `endpoint` forever; only returns if receiving fails.
+            fn serve<K: $crate::Kernel>(&mut self, endpoint: K::Handle) -> $crate::Error<K::Error>
+                where Self: Sized
+            {
+                $crate::rpc::serve::<K>(endpoint, |method, _args, reply| {
+                    $(
+                        if method == const { $crate::rpc::method_id(stringify!($method)) } {
+                            let ($($arg,)*): ($($ty,)*) = $crate::rpc::decode_args(_args)?;
+                            return $crate::rpc::encode_reply(&Ok(self.$method($($arg),*)), reply);
+                        }
+                    )*
+                    Err($crate::rpc::Fault::UnknownMethod)
+                })
+            }
+        }
+
+        /// Client stub for
+        #[doc = concat!("[`", stringify!($name), "`]")]
+        /// calls to the endpoint it was made for.
+        $vis struct $client<K: $crate::Kernel> {
+            endpoint: K::Handle,
+        }
+
+        impl<K: $crate::Kernel> Clone for $client<K> {
+            fn clone(&self) -> Self {
+                *self
+            }
+        }
+
+        impl<K: $crate::Kernel> Copy for $client<K> {}
+
+        impl<K: $crate::Kernel> $client<K> {
+            pub const fn new(endpoint: K::Handle) -> Self {
+                $client { endpoint }
+            }
+
+            $(
+                $(#[$mmeta])*
+                pub fn $method(&self $(, $arg: $ty)*)
+                    -> Result<$crate::__rpc_ret!($($ret)?), $crate::Error<K::Error>>
+                {
+                    const ID: u32 = $crate::rpc::method_id(stringify!($method));
+                    $crate::rpc::call::<K, _, _>(self.endpoint, ID, &($($arg,)*))
+                }
+            )*
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sim::Sim;
+    use crate::{Error, Fault, Kernel};
+    use serde::{Deserialize, Serialize};
+    use std::thread;
+
+    #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+    enum Op {
+        Add,
+        Div,
+    }
+
+    crate::rpc! {
+        /// A calculator with one register.
+        trait Calc => CalcClient {
+            fn apply(&mut self, op: Op, x: i32) -> Option<i32>;
+            fn reset(&mut self);
+            fn value(&mut self) -> i32;
+            fn dump(&mut self) -> [[i32; 32]; 8];
+        }
+    }
+
+    #[derive(Default)]
+    struct Register(i32);
+
+    impl Calc for Register {
+        fn apply(&mut self, op: Op, x: i32) -> Option<i32> {
+            self.0 = match op {
+                Op::Add => self.0.checked_add(x)?,
+                Op::Div => self.0.checked_div(x)?,
+            };
+            Some(self.0)
+        }
+
+        fn reset(&mut self) {
+            self.0 = 0;
+        }
+
+        fn value(&mut self) -> i32 {
+            self.0
+        }
+
+        fn dump(&mut self) -> [[i32; 32]; 8] {
+            [[self.0; 32]; 8] // more than MAX_MSG however it's encoded
+        }
+    }
+
+    fn spawn_server() -> u32 {
+        let endpoint = Sim::endpoint();
+        thread::spawn(move || Register::default().serve::<Sim>(endpoint));
+        endpoint
+    }
+
+    #[test]
+    fn calls_reach_the_server_and_return() {
+        let calc = CalcClient::<Sim>::new(spawn_server());
+        assert_eq!(calc.apply(Op::Add, 40), Ok(Some(40)));
+        assert_eq!(calc.apply(Op::Div, 0), Ok(None));
+        assert_eq!(calc.apply(Op::Div, -8), Ok(Some(-5)));
+        assert_eq!(calc.value(), Ok(-5));
+        assert_eq!(calc.reset(), Ok(()));
+        assert_eq!(calc.value(), Ok(0));
+    }
+
+    #[test]
+    fn clients_share_a_server() {
+        let endpoint = spawn_server();
+        let clients: Vec<_> = (0..4)
+            .map(|_| thread::spawn(move || {
+                let calc = CalcClient::<Sim>::new(endpoint);
+                for _ in 0..100 {
+                    calc.apply(Op::Add, 1).unwrap();
+                }
+            }))
+            .collect();
+        for c in clients {
+            c.join().unwrap();
+        }
+        assert_eq!(CalcClient::<Sim>::new(endpoint).value(), Ok(400));
+    }
+
+    #[test]
+    fn oversized_reply_is_a_fault() {
+        let calc = CalcClient::<Sim>::new(spawn_server());
+        assert_eq!(calc.dump(), Err(Error::Remote(Fault::ReplyTooLarge)));
+        // the server keeps going
+        assert_eq!(calc.value(), Ok(0));
+    }
+
+    #[test]
+    fn bad_requests_are_faults() {
+        let endpoint = spawn_server();
+        let mut reply = [0u8; 8];
+        let mut buf = [0u8; 16];
+
+        let n = Sim::call(endpoint, &[0xff], &mut reply).unwrap();
+        assert_eq!(postcard::from_bytes::<Result<(), Fault>>(&reply[..n]), Ok(Err(Fault::BadRequest)));
+
+        let unknown = postcard::to_slice(&(super::method_id("nope"), ()), &mut buf).unwrap();
+        let n = Sim::call(endpoint, unknown, &mut reply).unwrap();
+        assert_eq!(postcard::from_bytes::<Result<(), Fault>>(&reply[..n]), Ok(Err(Fault::UnknownMethod)));
+
+        // `apply` with its arguments missing
+        let short = postcard::to_slice(&(super::method_id("apply"), Op::Add), &mut buf).unwrap();
+        let n = Sim::call(endpoint, short, &mut reply).unwrap();
+        assert_eq!(postcard::from_bytes::<Result<(), Fault>>(&reply[..n]), Ok(Err(Fault::BadRequest)));
+    }
+}
@@ -0,0 +1,91 @@
+//! A stand-in kernel on std threads, for the host tests.
+//!
+//! Futexes are one lock and condvar for every word, which is slow but can't
+//! lose a wake.  Endpoints are a queue of pending calls each; a client waits
+//! for its reply to show up under its own id.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Condvar, Mutex};
+use crate::Kernel;
+
+pub struct Sim;
+
+#[derive(Default)]
+struct State {
+    endpoints: Vec<VecDeque<(u32, Vec<u8>)>>,
+    replies: HashMap<u32, Vec<u8>>,
+}
+
+static FUTEX: (Mutex<()>, Condvar) = (Mutex::new(()), Condvar::new());
+static IPC: Mutex<Option<State>> = Mutex::new(None);
+static IPC_CHANGED: Condvar = Condvar::new();
+static NEXT_CLIENT: AtomicU32 = AtomicU32::new(0);
+
+thread_local! {
+    static CLIENT_ID: u32 = NEXT_CLIENT.fetch_add(1, Ordering::Relaxed);
+}
+
+fn copy(src: &[u8], dst: &mut [u8]) -> usize {
+    let n = src.len().min(dst.len());
+    dst[..n].copy_from_slice(&src[..n]);
+    n
+}
+
+impl Sim {
+    /// A new endpoint nobody serves yet.
+    pub fn endpoint() -> u32 {
+        let mut ipc = IPC.lock().unwrap();
+        let state = ipc.get_or_insert_with(State::default);
+        state.endpoints.push(VecDeque::new());
+        state.endpoints.len() as u32 - 1
+    }
+}
+
+impl Kernel for Sim {
+    type Handle = u32;
+    type Error = ();
+
+    fn futex_wait(word: &AtomicU32, expected: u32) {
+        let guard = FUTEX.0.lock().unwrap();
+        if word.load(Ordering::SeqCst) == expected {
+            drop(FUTEX.1.wait(guard).unwrap());
+        }
+    }
+
+    fn futex_wake(_word: &AtomicU32, _count: u32) {
+        let _guard = FUTEX.0.lock().unwrap();
+        FUTEX.1.notify_all();
+    }
+
+    fn call(endpoint: u32, msg: &[u8], reply: &mut [u8]) -> Result<usize, ()> {
+        let me = CLIENT_ID.with(|id| *id);
+        let mut ipc = IPC.lock().unwrap();
+        ipc.as_mut().unwrap().endpoints.get_mut(endpoint as usize).ok_or(())?.push_back((me, msg.to_vec()));
+        IPC_CHANGED.notify_all();
+        loop {
+            if let Some(r) = ipc.as_mut().unwrap().replies.remove(&me) {
+                return Ok(copy(&r, reply));
+            }
+            ipc = IPC_CHANGED.wait(ipc).unwrap();
+        }
+    }
+
+    fn recv(endpoint: u32, buf: &mut [u8]) -> Result<(u32, usize), ()> {
+        let mut ipc = IPC.lock().unwrap();
+        loop {
+            let queue = ipc.as_mut().ok_or(())?.endpoints.get_mut(endpoint as usize).ok_or(())?;
+            if let Some((client, msg)) = queue.pop_front() {
+                return Ok((client, copy(&msg, buf)));
+            }
+            ipc = IPC_CHANGED.wait(ipc).unwrap();
+        }
+    }
+
+    fn reply(client: u32, msg: &[u8]) -> Result<(), ()> {
+        let mut ipc = IPC.lock().unwrap();
+        ipc.as_mut().ok_or(())?.replies.insert(client, msg.to_vec());
+        IPC_CHANGED.notify_all();
+        Ok(())
+    }
+}
@@ -10,17 +10,22 @@ MEMORY {
     * This is usually good for performance, as it distributes load on
     * those banks evenly.
     */
-  RAM : ORIGIN = 0x20000000, LENGTH = 512K
+  RAM : ORIGIN = 0x20000000, LENGTH = 504K - 256 - 32
+  /* --- where the running thread's heap is, user read-only (MPU region 5) --- */
+  HEAP_INFO : ORIGIN = 0x2007DEE0, LENGTH = 32
+  /* --- kernel boot info, survives everything but a power cycle --- */
+  PERSIST : ORIGIN = 0x2007DF00, LENGTH = 256
   /*
     * RAM banks 8 and 9 use a direct mapping. They can be used to have
     * memory areas dedicated for some specific job, improving predictability
     * of access times.
     * Example: Separate stacks for core0 and core1.
     */
-  /* --- dedicate SRAM4 (4 kB) for RTOS thread stacks --- */
-  THREAD_STACKS : ORIGIN = 0x20080000, LENGTH = 4K    /* uses the SRAM4 bank */
+  /* --- top 8 kB of striped RAM for RTOS thread stacks (8 x 1 kB) --- */
+  THREAD_STACKS : ORIGIN = 0x2007E000, LENGTH = 8K
 
-  SRAM5 : ORIGIN = 0x20081000, LENGTH = 4K
+  /* --- SRAM8 + SRAM9 (8 kB, direct-mapped) for per-thread heaps --- */
+  THREAD_HEAPS : ORIGIN = 0x20080000, LENGTH = 8K
 }
 
 SECTIONS {
@@ -44,11 +49,48 @@ SECTIONS {
     . = ALIGN(8);
     _thread_stacks_end = .;
   } > THREAD_STACKS
+
+  /* reset reason / boot counter, see muos-threads/src/bootinfo.rs */
+  .uninit.persist (NOLOAD) : ALIGN(4)
+  {
+    *(.uninit.persist .uninit.persist.*);
+  } > PERSIST
+
+  /* per-thread heaps for muos-alloc, one MPU region each */
+  .uninit.heaps (NOLOAD) : ALIGN(32)
+  {
+    _thread_heaps_start = .;
+    *(.uninit.heaps .uninit.heaps.*);
+    . = ALIGN(32);
+    _thread_heaps_end = .;
+  } > THREAD_HEAPS
+
+  /* see muos-threads/src/heap.rs */
+  .uninit.heap_info (NOLOAD) : ALIGN(32)
+  {
+    *(.uninit.heap_info .uninit.heap_info.*);
+  } > HEAP_INFO
 } INSERT AFTER .vector_table;
 
 /* move .text to start /after/ the boot info */
 _stext = ADDR(.start_block) + SIZEOF(.start_block);
 
+SECTIONS {
+  /* ### Secure gateway veneers
+    *
+    * Filled by the linker (--cmse-implib) with one SG stub per
+    * `nsc_exports!` function. Empty unless muos-threads' `trustzone`
+    * feature is on; the SAU marks it Non-secure callable.
+    */
+  .gnu.sgstubs : ALIGN(32)
+  {
+    __sg_start = .;
+    KEEP(*(.gnu.sgstubs*));
+    . = ALIGN(32);
+    __sg_end = .;
+  } > FLASH
+} INSERT AFTER .text;
+
 SECTIONS {
   /* ### Picotool 'Binary Info' Entries
     *
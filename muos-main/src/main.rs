@@ -12,10 +12,9 @@ use embedded_hal::digital::OutputPin;
 
 use embedded_hal::spi::SpiBus;
 use rp235x_hal::dma::{SingleChannel};
-use muos_syscall::sleep_ms;
 
-use muos_threads::scheduler::{spawn_thread, yield_now};
-use muos_threads::thread::ThreadFn;
+use muos_threads::scheduler::{sleep_ms, spawn_thread, spawn_thread_with, yield_now};
+use muos_threads::thread::{ThreadAttrs, ThreadFn};
 
 /// Tell the Boot ROM about our application
 #[link_section = ".start_block"]
@@ -70,9 +69,21 @@ fn main() -> ! {
 
   muos_threads::init(clocks.system_clock.freq().to_Hz(), &mut core);
 
+  // the kernel feeds it from now on, as long as no registered thread stalls
+  watchdog.pause_on_debug(true);
+  muos_threads::watchdog::init(cortex_m::singleton!(: hal::Watchdog = watchdog).unwrap(), 500);
+  let boot = muos_threads::boot_info();
+  if boot.reason == muos_threads::bootinfo::ResetReason::Watchdog && boot.detail != u32::MAX {
+    defmt::warn!("last reset: thread {} stalled", boot.detail);
+  }
+
   //defmt::trace!("before spawn thread 1");
-  spawn_thread(thread1 as ThreadFn);
+  //spawn_thread(thread1 as ThreadFn);
   //defmt::trace!("after spawn thread 1");
+  spawn_thread_with(thread_fp as ThreadFn, ThreadAttrs { fp: true, ..ThreadAttrs::default() });
+  // alternate privileged and unprivileged threads to check CONTROL survives switches
+  spawn_thread_with(thread_priv as ThreadFn, ThreadAttrs { privileged: true, ..ThreadAttrs::default() });
+  spawn_thread(thread_unpriv as ThreadFn);
   //spawn_thread(thread2 as ThreadFn);
   //defmt::trace!("after spawn thread 2");
 
@@ -110,6 +121,44 @@ fn thread3() {
   defmt::debug!("thread 3: {}", a);
 }
 
+fn thread_fp() {
+  // complementary filter; s16-s31 must survive the sleeps in between
+  let mut angle: f32 = 0.0;
+  let mut rate: f32 = 0.5;
+  for i in 0..100 {
+    angle = 0.98 * (angle + rate * 0.01) + 0.02 * (i as f32 * 0.1);
+    rate *= 1.01;
+    sleep_ms(10);
+  }
+
+  defmt::debug!("thread fp: angle={} rate={}", angle, rate);
+}
+
+fn thread_priv() {
+  for round in 0..10 {
+    check_privilege("priv", true, round);
+    // touching the SCB only works privileged
+    let _ = cortex_m::peripheral::SCB::vect_active();
+    yield_now();
+  }
+  defmt::debug!("thread priv: done");
+}
+
+fn thread_unpriv() {
+  for round in 0..10 {
+    check_privilege("unpriv", false, round);
+    yield_now();
+  }
+  defmt::debug!("thread unpriv: done");
+}
+
+fn check_privilege(name: &str, expect_privileged: bool, round: u32) {
+  let privileged = cortex_m::register::control::read().npriv().is_privileged();
+  if privileged != expect_privileged {
+    defmt::error!("thread {}: round {}: privileged={} expected {}", name, round, privileged, expect_privileged);
+  }
+}
+
 fn thread4() {
   defmt::debug!("THREAD 4");
 }
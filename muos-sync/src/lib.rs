@@ -0,0 +1,57 @@
+//! User-space synchronization on top of the `futex_wait` / `futex_wake`
+//! syscalls.
+//!
+//! The uncontended paths are a single atomic operation; a thread only traps
+//! into the kernel to sleep when it has to wait, or to wake someone who
+//! does.  The lock words are plain memory, so they have to live where every
+//! thread using them can reach it: the kernel refuses (`Fault`) futex calls
+//! on memory the caller's MPU regions don't cover.
+//!
+//! Channels are in `muos-ipc` (`muos_ipc::Channel`), on the same futexes.
+//!
+//! Built for the host, the futex calls go to a stand-in on std threads that
+//! counts them, which is what the unit tests run on:
+//!
+//! ```text
+//! cargo test -p muos-sync --target x86_64-unknown-linux-gnu
+//! ```
+
+#![cfg_attr(target_os = "none", no_std)]
+
+mod mutex;
+mod once;
+#[cfg(not(target_os = "none"))]
+mod sim;
+
+pub use crate::mutex::{Mutex, MutexGuard, RawMutex};
+pub use crate::once::Once;
+
+#[cfg(not(target_os = "none"))]
+use crate::sim::{futex_wait, futex_wake};
+
+#[cfg(target_os = "none")]
+use core::sync::atomic::AtomicU32;
+#[cfg(target_os = "none")]
+use muos_syscall::{SyscallError, UserPtr, WAIT_FOREVER};
+
+#[cfg(target_os = "none")]
+fn futex_ptr(word: &AtomicU32) -> UserPtr<u32> {
+    UserPtr::new(word.as_ptr() as usize)
+}
+
+/// Sleep while `word` reads `expected`. Returns on a wake, or right away if
+/// the word changed before the kernel got to look at it.
+#[cfg(target_os = "none")]
+fn futex_wait(word: &AtomicU32, expected: u32) {
+    match muos_syscall::futex_wait(futex_ptr(word), expected, WAIT_FOREVER) {
+        Ok(()) | Err(SyscallError::WouldBlock) => {}
+        Err(e) => panic!("futex_wait: {:?}", e),
+    }
+}
+
+#[cfg(target_os = "none")]
+fn futex_wake(word: &AtomicU32, count: u32) {
+    if let Err(e) = muos_syscall::futex_wake(futex_ptr(word), count) {
+        panic!("futex_wake: {:?}", e);
+    }
+}
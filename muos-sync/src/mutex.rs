@@ -0,0 +1,154 @@
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, Ordering};
+use crate::{futex_wait, futex_wake};
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+const CONTENDED: u32 = 2; // locked, and somebody may be asleep on it
+
+/// A lock word without data: the three-state futex mutex.
+pub struct RawMutex {
+    state: AtomicU32,
+}
+
+impl RawMutex {
+    pub const fn new() -> Self {
+        RawMutex { state: AtomicU32::new(UNLOCKED) }
+    }
+
+    pub fn try_lock(&self) -> bool {
+        self.state.compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed).is_ok()
+    }
+
+    pub fn lock(&self) {
+        if self.try_lock() {
+            return;
+        }
+        // mark it contended so the holder knows to wake us
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            futex_wait(&self.state, CONTENDED);
+        }
+    }
+
+    /// # Safety
+    /// The caller has to hold the lock.
+    pub unsafe fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            futex_wake(&self.state, 1);
+        }
+    }
+}
+
+impl Default for RawMutex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Mutual exclusion around a `T`, sleeping in the kernel only on contention.
+pub struct Mutex<T> {
+    raw: RawMutex,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Mutex { raw: RawMutex::new(), data: UnsafeCell::new(value) }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.raw.lock();
+        MutexGuard { mutex: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.raw.try_lock().then(|| MutexGuard { mutex: self })
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+/// Unlocks on drop.
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        unsafe { self.mutex.raw.unlock() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{tally, Tally};
+    use std::thread;
+
+    // statics, so no two tests share a futex word's address
+
+    #[test]
+    fn uncontended_lock_stays_out_of_the_kernel() {
+        static M: Mutex<u32> = Mutex::new(0);
+        for _ in 0..10 {
+            *M.lock() += 1;
+        }
+        assert_eq!(*M.lock(), 10);
+        assert_eq!(tally(&M.raw.state), Tally::default());
+    }
+
+    #[test]
+    fn contended_lock_sleeps_until_the_holder_wakes_it() {
+        static M: Mutex<u32> = Mutex::new(0);
+        let guard = M.lock();
+        let waiter = thread::spawn(|| *M.lock() += 1);
+        while tally(&M.raw.state).sleeping == 0 {
+            thread::yield_now();
+        }
+        assert_eq!(M.raw.state.load(Ordering::SeqCst), CONTENDED);
+        drop(guard);
+        waiter.join().unwrap();
+
+        // the waiter took it as CONTENDED, not knowing it was the only one,
+        // so its own unlock made a second wake
+        let t = tally(&M.raw.state);
+        assert_eq!((t.waits, t.wakes, t.sleeping), (1, 2, 0));
+        assert_eq!(*M.lock(), 1);
+        assert_eq!(M.raw.state.load(Ordering::SeqCst), UNLOCKED);
+    }
+
+    #[test]
+    fn many_threads_count_correctly() {
+        static M: Mutex<u32> = Mutex::new(0);
+        let threads: Vec<_> = (0..4).map(|_| thread::spawn(|| {
+            for _ in 0..10_000 {
+                *M.lock() += 1;
+            }
+        })).collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(*M.lock(), 40_000);
+        assert_eq!(tally(&M.raw.state).sleeping, 0);
+    }
+}
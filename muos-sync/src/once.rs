@@ -0,0 +1,94 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+use crate::{futex_wait, futex_wake};
+
+const INCOMPLETE: u32 = 0;
+const RUNNING: u32 = 1;
+const RUNNING_WAITERS: u32 = 2;
+const COMPLETE: u32 = 3;
+
+/// Run a piece of initialization exactly once; everyone else who shows up
+/// meanwhile sleeps until it's done.
+pub struct Once {
+    state: AtomicU32,
+}
+
+impl Once {
+    pub const fn new() -> Self {
+        Once { state: AtomicU32::new(INCOMPLETE) }
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+
+    pub fn call_once(&self, f: impl FnOnce()) {
+        if self.is_completed() {
+            return;
+        }
+        if self.state.compare_exchange(INCOMPLETE, RUNNING, Ordering::Acquire, Ordering::Acquire).is_ok() {
+            f();
+            if self.state.swap(COMPLETE, Ordering::Release) == RUNNING_WAITERS {
+                futex_wake(&self.state, u32::MAX);
+            }
+            return;
+        }
+        loop {
+            match self.state.load(Ordering::Acquire) {
+                COMPLETE => return,
+                RUNNING => {
+                    let _ = self.state.compare_exchange(RUNNING, RUNNING_WAITERS, Ordering::Relaxed, Ordering::Relaxed);
+                }
+                _ => futex_wait(&self.state, RUNNING_WAITERS),
+            }
+        }
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::tally;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn first_call_runs_and_later_ones_dont() {
+        static ONCE: Once = Once::new();
+        let mut runs = 0;
+        ONCE.call_once(|| runs += 1);
+        ONCE.call_once(|| runs += 1);
+        assert_eq!(runs, 1);
+        assert!(ONCE.is_completed());
+        assert_eq!(tally(&ONCE.state).waits, 0);
+    }
+
+    #[test]
+    fn latecomers_sleep_until_it_is_done() {
+        static ONCE: Once = Once::new();
+        let (started_tx, started_rx) = mpsc::channel();
+        let (finish_tx, finish_rx) = mpsc::channel::<()>();
+        let runner = thread::spawn(move || ONCE.call_once(|| {
+            started_tx.send(()).unwrap();
+            finish_rx.recv().unwrap();
+        }));
+        started_rx.recv().unwrap();
+        let latecomer = thread::spawn(|| ONCE.call_once(|| panic!("ran twice")));
+        while tally(&ONCE.state).sleeping == 0 {
+            thread::yield_now();
+        }
+        assert_eq!(ONCE.state.load(Ordering::SeqCst), RUNNING_WAITERS);
+
+        finish_tx.send(()).unwrap();
+        runner.join().unwrap();
+        latecomer.join().unwrap();
+        assert!(ONCE.is_completed());
+        let t = tally(&ONCE.state);
+        assert_eq!((t.waits, t.wakes, t.sleeping), (1, 1, 0));
+    }
+}
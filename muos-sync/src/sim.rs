@@ -0,0 +1,58 @@
+//! The futex syscalls on std threads, for the host tests.
+//!
+//! One lock and condvar for every word, which is slow but can't lose a wake,
+//! plus a tally per word of the calls that reached the "kernel".
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+/// What the kernel saw of one futex word.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub waits: u32,
+    pub wakes: u32,
+    /// Threads asleep on the word right now.
+    pub sleeping: u32,
+}
+
+static WORDS: Mutex<Vec<(usize, Tally)>> = Mutex::new(Vec::new());
+static CHANGED: Condvar = Condvar::new();
+
+fn entry<'a>(words: &'a mut MutexGuard<'_, Vec<(usize, Tally)>>, word: &AtomicU32) -> &'a mut Tally {
+    let addr = word.as_ptr() as usize;
+    let index = match words.iter().position(|(a, _)| *a == addr) {
+        Some(index) => index,
+        None => {
+            words.push((addr, Tally::default()));
+            words.len() - 1
+        }
+    };
+    &mut words[index].1
+}
+
+#[cfg(test)]
+pub fn tally(word: &AtomicU32) -> Tally {
+    *entry(&mut WORDS.lock().unwrap(), word)
+}
+
+pub fn futex_wait(word: &AtomicU32, expected: u32) {
+    let mut words = WORDS.lock().unwrap();
+    if word.load(Ordering::SeqCst) != expected {
+        return;
+    }
+    let t = entry(&mut words, word);
+    t.waits += 1;
+    t.sleeping += 1;
+    let wakes = t.wakes;
+    while entry(&mut words, word).wakes == wakes {
+        words = CHANGED.wait(words).unwrap();
+    }
+    entry(&mut words, word).sleeping -= 1;
+}
+
+/// Wakes everyone on the word; the callers loop anyway.
+pub fn futex_wake(word: &AtomicU32, _count: u32) {
+    let mut words = WORDS.lock().unwrap();
+    entry(&mut words, word).wakes += 1;
+    CHANGED.notify_all();
+}
@@ -0,0 +1,51 @@
+/// A value that travels through a syscall register.
+///
+/// Implemented for the plain integer types and raw pointers; the `syscalls!`
+/// macro uses it to marshal typed arguments into r0-r3 and results out of r0.
+pub trait SyscallArg: Sized {
+    fn into_reg(self) -> usize;
+    fn from_reg(reg: usize) -> Self;
+}
+
+macro_rules! impl_int_arg {
+    ($($t:ty),*) => {
+        $(
+            impl SyscallArg for $t {
+                #[inline(always)]
+                fn into_reg(self) -> usize { self as usize }
+                #[inline(always)]
+                fn from_reg(reg: usize) -> Self { reg as $t }
+            }
+        )*
+    };
+}
+
+impl_int_arg!(u8, u16, u32, usize, i8, i16, i32, isize);
+
+impl SyscallArg for () {
+    #[inline(always)]
+    fn into_reg(self) -> usize { 0 }
+    #[inline(always)]
+    fn from_reg(_: usize) -> Self {}
+}
+
+impl SyscallArg for bool {
+    #[inline(always)]
+    fn into_reg(self) -> usize { self as usize }
+    #[inline(always)]
+    fn from_reg(reg: usize) -> Self { reg != 0 }
+}
+
+impl<T> SyscallArg for *const T {
+    #[inline(always)]
+    fn into_reg(self) -> usize { self as usize }
+    #[inline(always)]
+    fn from_reg(reg: usize) -> Self { reg as *const T }
+}
+
+impl<T> SyscallArg for *mut T {
+    #[inline(always)]
+    fn into_reg(self) -> usize { self as usize }
+    #[inline(always)]
+    fn from_reg(reg: usize) -> Self { reg as *mut T }
+}
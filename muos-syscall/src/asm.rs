@@ -1,40 +1,107 @@
 use core::arch::asm;
 
+// Syscall ABI: the id goes in r12, up to four arguments in r0-r3 and a fifth
+// in r4.  The hardware doesn't stack r4, so SVCall picks it up on entry,
+// before anything could have used it.  The kernel writes the encoded result
+// into the stacked r0, which is the only register the caller sees change;
+// everything else comes back from the exception frame or was never touched.
+
 /// Fire an SVC with no arguments.
 #[inline(always)]
-pub fn syscall0(id: usize) {
+pub fn syscall0(id: usize) -> usize {
+    let ret;
+    unsafe {
+        asm!(
+        "svc 0",
+        in("r12") id,
+        lateout("r0") ret,
+        options(nostack)
+        );
+    }
+    ret
+}
+
+/// Fire an SVC with 1 argument in `r0`.
+#[inline(always)]
+pub fn syscall1(id: usize, a0: usize) -> usize {
+    let ret;
+    unsafe {
+        asm!(
+        "svc 0",
+        in("r12") id,
+        inlateout("r0") a0 => ret,
+        options(nostack)
+        );
+    }
+    ret
+}
+
+/// Fire an SVC with 2 arguments in `r0`/`r1`.
+#[inline(always)]
+pub fn syscall2(id: usize, a0: usize, a1: usize) -> usize {
+    let ret;
+    unsafe {
+        asm!(
+        "svc 0",
+        in("r12") id,
+        inlateout("r0") a0 => ret,
+        in("r1") a1,
+        options(nostack)
+        );
+    }
+    ret
+}
+
+/// Fire an SVC with 3 arguments in `r0`-`r2`.
+#[inline(always)]
+pub fn syscall3(id: usize, a0: usize, a1: usize, a2: usize) -> usize {
+    let ret;
     unsafe {
         asm!(
         "svc 0",
-        in("r0") id,
+        in("r12") id,
+        inlateout("r0") a0 => ret,
+        in("r1") a1,
+        in("r2") a2,
         options(nostack)
         );
     }
+    ret
 }
 
-/// Fire an SVC with 1 argument in `r1`.
+/// Fire an SVC with 4 arguments in `r0`-`r3`.
 #[inline(always)]
-pub fn syscall1(id: usize, a0: usize) {
+pub fn syscall4(id: usize, a0: usize, a1: usize, a2: usize, a3: usize) -> usize {
+    let ret;
     unsafe {
         asm!(
         "svc 0",
-        in("r0") id,
-        in("r1") a0,
+        in("r12") id,
+        inlateout("r0") a0 => ret,
+        in("r1") a1,
+        in("r2") a2,
+        in("r3") a3,
         options(nostack)
         );
     }
+    ret
 }
 
-/// Fire an SVC with 2 arguments in `r1`/`r2`.
+/// Fire an SVC with 5 arguments in `r0`-`r4`.
 #[inline(always)]
-pub fn syscall2(id: usize, a0: usize, a1: usize) {
+pub fn syscall5(id: usize, a0: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> usize {
+    let ret;
     unsafe {
         asm!(
         "svc 0",
-        in("r0") id,
-        in("r1") a0,
-        in("r2") a1,
+        in("r12") id,
+        inlateout("r0") a0 => ret,
+        in("r1") a1,
+        in("r2") a2,
+        in("r3") a3,
+        in("r4") a4,
         options(nostack)
         );
     }
+    ret
 }
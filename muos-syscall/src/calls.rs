@@ -0,0 +1,134 @@
+//! The syscall table.  Adding a syscall means adding one line here and one
+//! method to the kernel's `Syscalls` impl.
+
+use crate::handle::Handle;
+use crate::ipc::IpcCall;
+use crate::user::UserPtr;
+use crate::wait::WaitSpec;
+
+crate::syscalls! {
+    /// Start the scheduler and drop into the first thread.
+    SCHEDULER_BOOT = 0 => fn scheduler_boot();
+    /// Let the next ready thread run.
+    YIELD_NOW = 1 => fn yield_now();
+    /// Block the calling thread for at least `ms` milliseconds.
+    SLEEP_MS = 2 => fn sleep_ms(ms: u32);
+    /// Terminate the calling thread.
+    EXIT_THREAD = 3 => fn exit_thread();
+
+    /// Create a kernel mutex owned by the calling thread.
+    MUTEX_CREATE = 4 => fn mutex_create() -> Handle;
+    /// Lock `mutex`, blocking while another thread holds it, at most
+    /// `timeout_ms` (`WAIT_FOREVER` for no limit; `TimedOut` after it).
+    MUTEX_LOCK = 5 => fn mutex_lock(mutex: Handle, timeout_ms: u32);
+    /// Unlock `mutex`, handing it to the next waiter if there is one.
+    MUTEX_UNLOCK = 6 => fn mutex_unlock(mutex: Handle);
+    /// Destroy a kernel object created by the calling thread.
+    OBJECT_DESTROY = 7 => fn object_destroy(handle: Handle);
+
+    /// Unmask NVIC interrupt `irqn`, which the caller must have attached, and
+    /// block until it fires or `timeout_ms` passes (`WAIT_FOREVER` for no
+    /// limit).  The kernel masks the line again before the wake-up.
+    IRQ_WAIT = 8 => fn irq_wait(irqn: u16, timeout_ms: u32);
+    /// Route NVIC interrupt `irqn` to the calling thread.  The line stays
+    /// masked until the first `irq_wait`.  Unprivileged callers need an
+    /// `irq_grant` for it; lines with a vector of their own are refused.
+    IRQ_ATTACH = 9 => fn irq_attach(irqn: u16);
+
+    /// Stop `thread` until `thread_resume`; a blocked thread finishes its wait
+    /// first.  Unprivileged callers may only suspend themselves.
+    THREAD_SUSPEND = 10 => fn thread_suspend(thread: Handle);
+    /// Let a suspended `thread` run again.
+    THREAD_RESUME = 11 => fn thread_resume(thread: Handle);
+    /// Terminate `thread`, releasing its mutexes, objects and interrupt lines.
+    THREAD_KILL = 12 => fn thread_kill(thread: Handle);
+
+    /// Put the calling thread under the software watchdog: it must call
+    /// `wdt_feed` at least every `timeout_ms` or the system is reset.
+    WDT_REGISTER = 13 => fn wdt_register(timeout_ms: u32);
+    /// Restart the calling thread's watchdog deadline.
+    WDT_FEED = 14 => fn wdt_feed();
+
+    /// Reset the system, leaving `code` in the boot info of the next boot.
+    /// Privileged threads only.
+    SYSTEM_RESET = 15 => fn system_reset(code: u32);
+
+    /// Finish the calling periodic thread's job and block until its next
+    /// release.  Fails for threads without EDF parameters.
+    PERIOD_WAIT = 16 => fn period_wait();
+
+    /// Create a condition variable owned by the calling thread.
+    COND_CREATE = 17 => fn cond_create() -> Handle;
+    /// Unlock `mutex` and wait on `cond` in one step, then lock `mutex` again
+    /// before returning, whether signalled, timed out (`TimedOut`) or because
+    /// `cond` was destroyed (`ObjectRemoved`).  The caller must hold `mutex`,
+    /// and all waiters on `cond` must use the same one.
+    COND_WAIT = 18 => fn cond_wait(cond: Handle, mutex: Handle, timeout_ms: u32);
+    /// Wake the highest-priority thread waiting on `cond`, if any.
+    COND_SIGNAL = 19 => fn cond_signal(cond: Handle);
+    /// Wake every thread waiting on `cond`.
+    COND_BROADCAST = 20 => fn cond_broadcast(cond: Handle);
+
+    /// Create a reader-writer lock owned by the calling thread.  With
+    /// `prefer_writer` a waiting writer holds off new readers.
+    RWLOCK_CREATE = 21 => fn rwlock_create(prefer_writer: bool) -> Handle;
+    /// Take `lock` shared, blocking while a writer has it or, with writer
+    /// preference, waits for it.
+    RWLOCK_READ = 22 => fn rwlock_read(lock: Handle, timeout_ms: u32);
+    /// Take `lock` exclusively, blocking while anyone else has it.
+    RWLOCK_WRITE = 23 => fn rwlock_write(lock: Handle, timeout_ms: u32);
+    /// Drop the caller's read or write hold on `lock`.
+    RWLOCK_UNLOCK = 24 => fn rwlock_unlock(lock: Handle);
+
+    /// Sleep on the word at `addr` as long as it still reads `expected`;
+    /// `WouldBlock` right away if it doesn't.  `addr` has to be 4-aligned
+    /// memory the caller may access.
+    FUTEX_WAIT = 25 => fn futex_wait(addr: UserPtr<u32>, expected: u32, timeout_ms: u32);
+    /// Wake up to `count` threads sleeping on `addr`; returns how many woke.
+    FUTEX_WAKE = 26 => fn futex_wake(addr: UserPtr<u32>, count: u32) -> u32;
+
+    /// Raw form of `wait::wait_many`: `count` specs at `specs`.  Returns the
+    /// index of the one that fired.
+    WAIT_MANY = 27 => fn wait_many_raw(specs: UserPtr<WaitSpec>, count: u32, timeout_ms: u32) -> u32;
+
+    /// Create an IPC endpoint owned by the calling thread.
+    ENDPOINT_CREATE = 28 => fn endpoint_create() -> Handle;
+    /// Raw form of `ipc::ipc_call`; returns the reply length.
+    IPC_CALL = 29 => fn ipc_call_raw(endpoint: Handle, call: UserPtr<IpcCall>, timeout_ms: u32) -> u32;
+    /// Raw form of `ipc::ipc_recv`: the message goes to `buf`, its length to
+    /// `len`.  Returns the client.
+    IPC_RECV = 30 => fn ipc_recv_raw(endpoint: Handle, buf: UserPtr<u8>, cap: u32, len: UserPtr<u32>, timeout_ms: u32) -> Handle;
+    /// Raw form of `ipc::ipc_reply`.
+    IPC_REPLY = 31 => fn ipc_reply_raw(client: Handle, msg: UserPtr<u8>, len: u32);
+
+    /// Create a stream buffer of `capacity` bytes owned by the calling
+    /// thread; readers wake once `trigger` bytes are in.
+    STREAM_CREATE = 32 => fn stream_create(capacity: u32, trigger: u32) -> Handle;
+    /// Raw form of `stream::stream_write`; returns the bytes written.
+    STREAM_WRITE = 33 => fn stream_write_raw(stream: Handle, data: UserPtr<u8>, len: u32, timeout_ms: u32) -> u32;
+    /// Raw form of `stream::stream_read`; returns the bytes read.
+    STREAM_READ = 34 => fn stream_read_raw(stream: Handle, buf: UserPtr<u8>, cap: u32, timeout_ms: u32) -> u32;
+
+    /// Raw form of `notify::notify`; `action` is a `NotifyAction`.
+    NOTIFY = 35 => fn notify_raw(thread: Handle, value: u32, action: u32);
+    /// Raw form of `notify::notify_wait`: the word goes to `value`.
+    NOTIFY_WAIT = 36 => fn notify_wait_raw(clear_on_entry: u32, clear_on_exit: u32, value: UserPtr<u32>, timeout_ms: u32);
+
+    /// Create a barrier for `parties` threads, owned by the calling thread.
+    BARRIER_CREATE = 37 => fn barrier_create(parties: u32) -> Handle;
+    /// Block until `parties` threads are waiting on `barrier`, then release
+    /// them all; returns 1 to exactly one of them, 0 to the rest.  Once a
+    /// thread that waited on it exits, the barrier is broken and every wait
+    /// fails with `OwnerDead`.
+    BARRIER_WAIT = 38 => fn barrier_wait(barrier: Handle, timeout_ms: u32) -> u32;
+    /// Create a one-shot latch that opens after `count` count-downs.
+    LATCH_CREATE = 39 => fn latch_create(count: u32) -> Handle;
+    /// Count `latch` down by one, opening it at zero.
+    LATCH_COUNT_DOWN = 40 => fn latch_count_down(latch: Handle);
+    /// Block until `latch` is open; `OwnerDead` if its creator exits first.
+    LATCH_WAIT = 41 => fn latch_wait(latch: Handle, timeout_ms: u32);
+
+    /// Let the unprivileged `thread` `irq_attach` NVIC interrupt `irqn`.
+    /// Privileged threads only.
+    IRQ_GRANT = 42 => fn irq_grant(irqn: u16, thread: Handle);
+}
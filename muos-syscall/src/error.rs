@@ -0,0 +1,88 @@
+/// Errors a syscall can report back to the caller.
+///
+/// The discriminants follow the usual errno numbering; on the wire an error
+/// is returned in r0 as the negated code, anything else is a success value.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, defmt::Format)]
+#[repr(usize)]
+pub enum SyscallError {
+    /// EPERM: the caller isn't allowed to do this.
+    NotPermitted = 1,
+    /// ESRCH: no such thread.
+    NoSuchThread = 3,
+    /// EAGAIN: resource temporarily unavailable, try again.
+    WouldBlock = 11,
+    /// ENOMEM: out of kernel memory / slots.
+    NoMemory = 12,
+    /// EFAULT: a pointer argument is outside the caller's memory.
+    Fault = 14,
+    /// EBUSY: resource is in use.
+    Busy = 16,
+    /// EINVAL: an argument is out of range, or a handle doesn't resolve.
+    InvalidArgument = 22,
+    /// EDEADLK: the call would deadlock the caller (e.g. relocking its own mutex).
+    Deadlock = 35,
+    /// ENOSYS: no handler registered for this syscall id.
+    NoSys = 38,
+    /// EIDRM: the object was destroyed while the caller waited on it.
+    ObjectRemoved = 43,
+    /// ETIMEDOUT: a blocking call ran out of time.
+    TimedOut = 110,
+    /// EOWNERDEAD: a thread the caller was waiting on went away, e.g. a
+    /// barrier participant.
+    OwnerDead = 130,
+    /// EIOCBQUEUED: kernel-internal, never reaches the caller.  A handler
+    /// that blocked the caller returns it so the dispatcher leaves the
+    /// stacked r0 to whoever wakes the thread.
+    Blocked = 529,
+}
+
+pub type SyscallResult = Result<usize, SyscallError>;
+
+/// Largest errno we encode; raw values in `-MAX_ERRNO..0` are errors.
+const MAX_ERRNO: usize = 4095;
+
+impl SyscallError {
+    pub fn errno(self) -> usize {
+        self as usize
+    }
+
+    pub fn from_errno(errno: usize) -> Option<Self> {
+        Some(match errno {
+            1 => SyscallError::NotPermitted,
+            3 => SyscallError::NoSuchThread,
+            11 => SyscallError::WouldBlock,
+            12 => SyscallError::NoMemory,
+            14 => SyscallError::Fault,
+            16 => SyscallError::Busy,
+            22 => SyscallError::InvalidArgument,
+            35 => SyscallError::Deadlock,
+            38 => SyscallError::NoSys,
+            43 => SyscallError::ObjectRemoved,
+            110 => SyscallError::TimedOut,
+            130 => SyscallError::OwnerDead,
+            _ => return None,
+        })
+    }
+}
+
+/// Pack a handler result into the value written back to the caller's r0.
+pub fn encode(result: SyscallResult) -> usize {
+    match result {
+        Ok(value) => {
+            debug_assert!(value <= usize::MAX - MAX_ERRNO, "syscall value collides with an errno");
+            value
+        }
+        Err(e) => e.errno().wrapping_neg(),
+    }
+}
+
+/// Unpack r0 after an `svc` back into a `Result`.
+pub fn decode(raw: usize) -> SyscallResult {
+    let errno = raw.wrapping_neg();
+    if errno != 0 && errno <= MAX_ERRNO {
+        // the kernel only ever encodes known codes; anything else is a broken ABI
+        Err(SyscallError::from_errno(errno).unwrap_or(SyscallError::InvalidArgument))
+    } else {
+        Ok(raw)
+    }
+}
@@ -0,0 +1,80 @@
+use crate::arg::SyscallArg;
+
+/// Kinds of kernel objects a `Handle` can name.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, defmt::Format)]
+#[repr(u8)]
+pub enum ObjectKind {
+    Mutex = 1,
+    Thread = 2,
+    Cond = 3,
+    RwLock = 4,
+    Endpoint = 5,
+    Stream = 6,
+    Barrier = 7,
+    Latch = 8,
+}
+
+impl ObjectKind {
+    pub fn from_raw(raw: u8) -> Option<Self> {
+        Some(match raw {
+            1 => ObjectKind::Mutex,
+            2 => ObjectKind::Thread,
+            3 => ObjectKind::Cond,
+            4 => ObjectKind::RwLock,
+            5 => ObjectKind::Endpoint,
+            6 => ObjectKind::Stream,
+            7 => ObjectKind::Barrier,
+            8 => ObjectKind::Latch,
+            _ => return None,
+        })
+    }
+}
+
+const INDEX_BITS: u32 = 12;
+const KIND_BITS: u32 = 4;
+const GEN_SHIFT: u32 = INDEX_BITS + KIND_BITS;
+
+/// Generation counters are 15 bits wide so a handle never lands in the errno
+/// range of a syscall return value, and never 0 so a zero handle is invalid.
+pub const MAX_GENERATION: u16 = 0x7FFF;
+
+/// Opaque name of a kernel object: `gen:15 | kind:4 | index:12`.
+///
+/// The generation is bumped every time a slot is freed, so a handle kept
+/// around after its object was destroyed no longer resolves.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, defmt::Format)]
+#[repr(transparent)]
+pub struct Handle(u32);
+
+impl Handle {
+    pub const fn new(kind: ObjectKind, index: usize, generation: u16) -> Self {
+        Handle(((generation as u32) << GEN_SHIFT) | ((kind as u32) << INDEX_BITS) | index as u32)
+    }
+
+    pub const fn from_raw(raw: u32) -> Self {
+        Handle(raw)
+    }
+
+    pub const fn raw(self) -> u32 {
+        self.0
+    }
+
+    pub fn kind(self) -> Option<ObjectKind> {
+        ObjectKind::from_raw(((self.0 >> INDEX_BITS) & ((1 << KIND_BITS) - 1)) as u8)
+    }
+
+    pub const fn index(self) -> usize {
+        (self.0 & ((1 << INDEX_BITS) - 1)) as usize
+    }
+
+    pub const fn generation(self) -> u16 {
+        (self.0 >> GEN_SHIFT) as u16
+    }
+}
+
+impl SyscallArg for Handle {
+    #[inline(always)]
+    fn into_reg(self) -> usize { self.0 as usize }
+    #[inline(always)]
+    fn from_reg(reg: usize) -> Self { Handle(reg as u32) }
+}
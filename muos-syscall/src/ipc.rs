@@ -0,0 +1,61 @@
+//! Synchronous IPC: a client `ipc_call`s an endpoint and blocks until a
+//! server thread `ipc_recv`s the message and `ipc_reply`s to it.
+//!
+//! The kernel copies the bytes straight from one thread's buffer into the
+//! other's; nothing is queued in between.  While a server holds a call it
+//! runs at least at its client's priority.
+
+use crate::error::SyscallError;
+use crate::handle::Handle;
+use crate::user::UserPtr;
+
+/// Largest message, request or reply, in bytes.
+pub const MAX_IPC_MSG: usize = 128;
+
+/// Buffers of an `ipc_call`, as the kernel reads them from user memory.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct IpcCall {
+    pub msg: u32,
+    pub msg_len: u32,
+    pub reply: u32,
+    pub reply_cap: u32,
+}
+
+/// Send `msg` to whoever serves `endpoint` and block for the reply.
+/// Returns the reply's length; a longer reply is cut to `reply.len()`.
+///
+/// `timeout_ms` (`WAIT_FOREVER` for no limit) covers the whole call, the
+/// server's work included; after it the call fails with `TimedOut` and the
+/// server's `ipc_reply` with `NotPermitted`.
+pub fn ipc_call(endpoint: Handle, msg: &[u8], reply: &mut [u8], timeout_ms: u32) -> Result<usize, SyscallError> {
+    let call = IpcCall {
+        msg: msg.as_ptr() as u32,
+        msg_len: msg.len() as u32,
+        reply: reply.as_mut_ptr() as u32,
+        reply_cap: reply.len() as u32,
+    };
+    crate::ipc_call_raw(endpoint, UserPtr::new(&call as *const IpcCall as usize), timeout_ms)
+        .map(|len| len as usize)
+}
+
+/// Block until a call comes in on `endpoint`, at most `timeout_ms`
+/// (`WAIT_FOREVER` for no limit).  Returns the calling thread, to pass to
+/// `ipc_reply`, and the message's length; a longer message is cut to
+/// `buf.len()`.
+pub fn ipc_recv(endpoint: Handle, buf: &mut [u8], timeout_ms: u32) -> Result<(Handle, usize), SyscallError> {
+    let mut len = 0u32;
+    let client = crate::ipc_recv_raw(
+        endpoint,
+        UserPtr::new(buf.as_mut_ptr() as usize),
+        buf.len() as u32,
+        UserPtr::new(&mut len as *mut u32 as usize),
+        timeout_ms,
+    )?;
+    Ok((client, len as usize))
+}
+
+/// Answer the call `client` is blocked in, letting it run again.
+pub fn ipc_reply(client: Handle, msg: &[u8]) -> Result<(), SyscallError> {
+    crate::ipc_reply_raw(client, UserPtr::new(msg.as_ptr() as usize), msg.len() as u32)
+}
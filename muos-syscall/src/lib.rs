@@ -1,16 +1,38 @@
 #![feature(naked_functions, asm)]
 #![no_std]
 
-mod asm;
+mod macros;
+pub mod asm;
+pub mod arg;
+mod calls;
+pub mod error;
+pub mod handle;
+pub mod ipc;
+pub mod notify;
 pub mod numbers;
+pub mod stream;
+pub mod user;
+pub mod wait;
 
 use core::arch::{asm, naked_asm};
-use crate::asm::{syscall0, syscall1};
 use crate::numbers::MAX_SYSCALL_ID;
 
+pub use crate::arg::SyscallArg;
+pub use crate::calls::*;
+pub use crate::error::{SyscallError, SyscallResult};
+pub use crate::handle::{Handle, ObjectKind};
+pub use crate::ipc::{ipc_call, ipc_recv, ipc_reply, IpcCall, MAX_IPC_MSG};
+pub use crate::notify::{notify, notify_wait, NotifyAction};
+pub use crate::stream::{stream_read, stream_write, MAX_STREAM_LEN};
+pub use crate::user::{UserPtr, UserSlice};
+pub use crate::wait::{wait_many, WaitKind, WaitSpec, MAX_WAIT_SPECS};
 
-/// Signature for a syscall handler.
-pub type SyscallFn = unsafe extern "C" fn(usize, usize, usize);
+/// Signature for a syscall handler: the caller's r0-r4 in, result out.
+pub type SyscallFn = unsafe fn(usize, usize, usize, usize, usize) -> SyscallResult;
+
+/// Timeout for blocking calls that should never give up.  So is any timeout
+/// of 2^31 ms (about 24.8 days) or more; sleeps that long are cut to that.
+pub const WAIT_FOREVER: u32 = u32::MAX;
 
 /// The central dispatch table.
 static mut HANDLERS: [Option<SyscallFn>; MAX_SYSCALL_ID] = [None; MAX_SYSCALL_ID];
@@ -30,49 +52,88 @@ pub fn get(id: usize) -> Option<SyscallFn> {
     }
 }
 
-#[inline(always)]
-pub fn scheduler_boot() {
-    syscall0(numbers::SCHEDULER_BOOT)
-}
-
-#[inline(always)]
-pub fn yield_now() {
-    syscall0(numbers::YIELD_NOW)
+/// Hardware-stacked exception frame of the thread that issued the `svc`.
+#[repr(C)]
+pub struct ExceptionFrame {
+    pub r0: usize,
+    pub r1: usize,
+    pub r2: usize,
+    pub r3: usize,
+    pub r12: usize,
+    pub lr: usize,
+    pub pc: usize,
+    pub xpsr: usize,
 }
 
-#[inline(always)]
-pub fn sleep_ms(ms: u32) {
-    syscall1(numbers::SLEEP_MS, ms as usize)
+/// Naked SVC entrypoint.  Finds the caller's stacked frame and jumps to
+/// `syscall_dispatcher` with it and the caller's r4, which is still live.
+#[cfg(not(feature = "nonsecure"))]
+#[naked]
+#[no_mangle]
+pub unsafe extern "C" fn SVCall() -> ! {
+    naked_asm!(
+    // EXC_RETURN bit 2 tells which stack the caller's frame went onto
+    "tst   lr, #4",
+    "ite   eq",
+    "mrseq r0, MSP",
+    "mrsne r0, PSP",
+    "mov   r1, r4",
+    // tail‑call into dispatcher(frame, r4)
+    "b {disp}",
+    disp = sym syscall_dispatcher,
+    )
 }
 
-#[inline(always)]
-pub fn exit_thread() {
-    syscall0(numbers::EXIT_THREAD)
+#[cfg(feature = "nonsecure")]
+extern "C" {
+    /// NSC veneer exported by the Secure kernel.
+    fn muos_ns_svc(frame: *mut ExceptionFrame, a4: usize);
 }
 
-/// Naked SVC entrypoint.  Reads the mailbox and jumps to `syscall_dispatcher`.
+/// Non-secure SVC entrypoint.  The kernel lives in the Secure world, so hand
+/// the stacked frame and r4 over through the NSC gate and return with the
+/// result in r0.
+#[cfg(feature = "nonsecure")]
 #[naked]
 #[no_mangle]
 pub unsafe extern "C" fn SVCall() -> ! {
     naked_asm!(
-    // tail‑call into dispatcher(id,r1,r2,r3)
-    "b {disp}",
-    disp = sym syscall_dispatcher,
+    "tst   lr, #4",
+    "ite   eq",
+    "mrseq r0, MSP",
+    "mrsne r0, PSP",
+    "mov   r1, r4",
+    "push  {{r4, lr}}",
+    "bl    {gate}",
+    "pop   {{r4, pc}}",
+    gate = sym muos_ns_svc,
     )
 }
 
-/// Dispatches syscalls.  Looks up the handler and calls it.
+/// Dispatches syscalls.  Looks up the handler, calls it with the stacked
+/// arguments and `a4`, the caller's r4, and writes the encoded result back into the stacked r0, unless
+/// the handler blocked the caller (`SyscallError::Blocked`): then the wake
+/// delivers the result, and an ISR may already have done so by now.
+///
+/// # Safety
+/// `frame` has to be the exception frame the calling thread stacked on its
+/// way into SVCall, and this has to run while that SVC is being handled.
 #[no_mangle]
-pub unsafe extern "C" fn syscall_dispatcher(
-    id: usize,    // r0
-    a1: usize,    // r1
-    a2: usize,    // r2
-    a3: usize,    // r3
-) {
-    //defmt::trace!("syscall dispatch: {:#x} {:#x} {:#x} {:#x}", id, a1, a2, a3);
-    if let Some(f) = get(id) {
-        f(a1, a2, a3)
-    } else {
-        panic!("syscall_dispatcher: no handler registered for id {}", id)
+pub unsafe extern "C" fn syscall_dispatcher(frame: *mut ExceptionFrame, a4: usize) {
+    let frame = &mut *frame;
+    let id = frame.r12;
+    //defmt::trace!("syscall dispatch: {:#x} {:#x} {:#x} {:#x} {:#x}", id, frame.r0, frame.r1, frame.r2, frame.r3);
+
+    let result = match get(id) {
+        Some(f) => f(frame.r0, frame.r1, frame.r2, frame.r3, a4),
+        None => {
+            defmt::warn!("syscall_dispatcher: no handler registered for id {}", id);
+            Err(SyscallError::NoSys)
+        }
+    };
+
+    match result {
+        Err(SyscallError::Blocked) => {}
+        result => frame.r0 = error::encode(result),
     }
 }
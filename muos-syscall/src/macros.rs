@@ -0,0 +1,103 @@
+/// Declare the syscall table in one place.
+///
+/// ```ignore
+/// syscalls! {
+///     /// Put the calling thread to sleep.
+///     SLEEP_MS = 2 => fn sleep_ms(ms: u32);
+/// }
+/// ```
+///
+/// For every entry this generates:
+/// - a variant of the `Sysno` enum carrying the id (a repeated id is a
+///   duplicate-discriminant compile error, an id `>= MAX_SYSCALL_ID` fails a
+///   const assert),
+/// - a user-side stub `fn sleep_ms(ms: u32) -> Result<(), SyscallError>`,
+/// - a method on the `Syscalls` trait the kernel implements,
+/// - a typed trampoline registered by `install::<Kernel>()`.
+///
+/// At most five arguments: four in r0-r3 and one in r4 (see `asm`).  Calls
+/// with four or fewer leave r4 alone.  The return type defaults to `()`.
+#[macro_export]
+macro_rules! syscalls {
+    ($(
+        $(#[$meta:meta])*
+        $nr:ident = $id:literal => fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)?;
+    )*) => {
+        /// Syscall ids, passed in r12.
+        #[allow(non_camel_case_types)]
+        #[derive(Copy, Clone, PartialEq, Eq)]
+        #[repr(usize)]
+        pub enum Sysno {
+            $($nr = $id,)*
+        }
+
+        $(
+            const _: () = assert!(
+                $id < $crate::numbers::MAX_SYSCALL_ID,
+                concat!("syscall ", stringify!($nr), " is out of range, bump MAX_SYSCALL_ID")
+            );
+            const _: () = assert!(
+                <[&str]>::len(&[$(stringify!($arg)),*]) <= 5,
+                concat!("syscall ", stringify!($nr), " takes more than 5 arguments")
+            );
+
+            $(#[$meta])*
+            #[inline(always)]
+            #[allow(unused_mut)]
+            pub fn $name($($arg: $ty),*) -> Result<$crate::__syscall_ret!($($ret)?), $crate::SyscallError> {
+                let mut regs = [0usize; 5];
+                let mut _next = 0;
+                $(
+                    regs[_next] = <$ty as $crate::SyscallArg>::into_reg($arg);
+                    _next += 1;
+                )*
+                let raw = if <[&str]>::len(&[$(stringify!($arg)),*]) == 5 {
+                    $crate::asm::syscall5(Sysno::$nr as usize, regs[0], regs[1], regs[2], regs[3], regs[4])
+                } else {
+                    $crate::asm::syscall4(Sysno::$nr as usize, regs[0], regs[1], regs[2], regs[3])
+                };
+                $crate::error::decode(raw)
+                    .map(<$crate::__syscall_ret!($($ret)?) as $crate::SyscallArg>::from_reg)
+            }
+        )*
+
+        /// Kernel-side implementation of every declared syscall.  Methods run
+        /// in handler mode from the SVC dispatcher.
+        pub trait Syscalls {
+            $(
+                $(#[$meta])*
+                ///
+                /// # Safety
+                /// Only for the SVC dispatcher, which calls it on behalf of
+                /// the thread that trapped.
+                unsafe fn $name($($arg: $ty),*) -> Result<$crate::__syscall_ret!($($ret)?), $crate::SyscallError>;
+            )*
+        }
+
+        /// Register a marshalling trampoline for every syscall of `S`.
+        pub fn install<S: Syscalls>() {
+            $(
+                {
+                    unsafe fn trampoline<S: Syscalls>(a0: usize, a1: usize, a2: usize, a3: usize, a4: usize) -> $crate::SyscallResult {
+                        let _regs = [a0, a1, a2, a3, a4];
+                        let mut _next = 0;
+                        S::$name($({
+                            let v = <$ty as $crate::SyscallArg>::from_reg(_regs[_next]);
+                            _next += 1;
+                            v
+                        }),*)
+                            .map($crate::SyscallArg::into_reg)
+                    }
+                    $crate::register(Sysno::$nr as usize, trampoline::<S>);
+                }
+            )*
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __syscall_ret {
+    () => { () };
+    ($ret:ty) => { $ret };
+}
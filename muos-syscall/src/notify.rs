@@ -0,0 +1,45 @@
+//! Per-thread notification words: a cheap signal straight to one thread,
+//! with no kernel object in between.
+
+use crate::error::SyscallError;
+use crate::handle::Handle;
+use crate::user::UserPtr;
+
+/// How `notify` changes the target's notification word.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, defmt::Format)]
+#[repr(u32)]
+pub enum NotifyAction {
+    /// OR `value` into the word, e.g. one bit per event source.
+    SetBits = 0,
+    /// Add one, like giving a counting semaphore; `value` is ignored.
+    Increment = 1,
+    /// Replace the word with `value`, taken or not.
+    Overwrite = 2,
+}
+
+impl NotifyAction {
+    pub fn from_raw(raw: u32) -> Option<Self> {
+        Some(match raw {
+            0 => NotifyAction::SetBits,
+            1 => NotifyAction::Increment,
+            2 => NotifyAction::Overwrite,
+            _ => return None,
+        })
+    }
+}
+
+/// Update `thread`'s notification word and mark it pending, waking the
+/// thread if it sits in `notify_wait`.
+pub fn notify(thread: Handle, value: u32, action: NotifyAction) -> Result<(), SyscallError> {
+    crate::notify_raw(thread, value, action as u32)
+}
+
+/// Take the caller's notification, sleeping until there is one.  With none
+/// pending, the bits in `clear_on_entry` are cleared first; once taken, the
+/// word is returned and the bits in `clear_on_exit` cleared (`u32::MAX`
+/// resets it).  `WouldBlock` for a zero timeout with nothing pending.
+pub fn notify_wait(clear_on_entry: u32, clear_on_exit: u32, timeout_ms: u32) -> Result<u32, SyscallError> {
+    let mut value = 0u32;
+    crate::notify_wait_raw(clear_on_entry, clear_on_exit, UserPtr::new(&mut value as *mut u32 as usize), timeout_ms)?;
+    Ok(value)
+}
@@ -0,0 +1,29 @@
+//! Stream buffers: a byte pipe for data that comes in bursts of any length,
+//! like a UART's receive side.
+//!
+//! There are no message boundaries: one write can come out as several reads
+//! and the other way round.  A reader sleeps until the stream holds the
+//! trigger level (or as much as its buffer takes, if that's less).  The
+//! kernel's `stream::isr_write` feeds a stream from an interrupt handler.
+
+use crate::error::SyscallError;
+use crate::handle::Handle;
+use crate::user::UserPtr;
+
+/// Largest stream capacity, in bytes.
+pub const MAX_STREAM_LEN: usize = 256;
+
+/// Write all of `data`, sleeping while the stream is full.  Returns how much
+/// went in; short only if `timeout_ms` ran out, and `TimedOut` if nothing did.
+pub fn stream_write(stream: Handle, data: &[u8], timeout_ms: u32) -> Result<usize, SyscallError> {
+    crate::stream_write_raw(stream, UserPtr::new(data.as_ptr() as usize), data.len() as u32, timeout_ms)
+        .map(|n| n as usize)
+}
+
+/// Read up to `buf.len()` bytes once the trigger level is reached.  If
+/// `timeout_ms` runs out first, returns what's there, and `TimedOut` if
+/// that's nothing.
+pub fn stream_read(stream: Handle, buf: &mut [u8], timeout_ms: u32) -> Result<usize, SyscallError> {
+    crate::stream_read_raw(stream, UserPtr::new(buf.as_mut_ptr() as usize), buf.len() as u32, timeout_ms)
+        .map(|n| n as usize)
+}
@@ -0,0 +1,316 @@
+//! Checked access to memory handed in by a thread.
+//!
+//! Syscall handlers run privileged in handler mode, so a pointer argument has
+//! to be checked against what the *caller* may touch before the kernel
+//! dereferences it.  The check asks the MPU itself through the ARMv8-M `TT`
+//! instruction: `TTT` reports the permissions an unprivileged access would
+//! get, which covers the caller's stack region (programmed from
+//! `get_current_thread_stack` on every switch) and any other region granted
+//! to user mode.  Privileged callers are checked with plain `TT`.
+//!
+//! With the `trustzone` feature a Non-secure thread's syscalls come in
+//! through the Secure kernel's gate, which runs the dispatcher under
+//! `as_nonsecure_caller`.  Its pointers are then checked with `TTA`/`TTAT`
+//! against the SAU and the Non-secure MPU instead, so it can't hand the kernel
+//! a Secure address.
+
+use core::arch::asm;
+#[cfg(feature = "trustzone")]
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+use crate::arg::SyscallArg;
+use crate::error::SyscallError;
+
+// TT response fields
+const TT_MREGION_MASK: u32 = 0xFF;
+const TT_MRVALID: u32 = 1 << 16;
+const TT_R: u32 = 1 << 18;
+const TT_RW: u32 = 1 << 19;
+#[cfg(feature = "trustzone")]
+const TT_SREGION_SHIFT: u32 = 8;
+#[cfg(feature = "trustzone")]
+const TT_SRVALID: u32 = 1 << 17;
+#[cfg(feature = "trustzone")]
+const TT_NSR: u32 = 1 << 20;
+#[cfg(feature = "trustzone")]
+const TT_NSRW: u32 = 1 << 21;
+#[cfg(feature = "trustzone")]
+const TT_S: u32 = 1 << 22;
+
+/// Set while the dispatcher runs for a Non-secure thread.
+#[cfg(feature = "trustzone")]
+static NONSECURE_CALLER: AtomicBool = AtomicBool::new(false);
+
+#[inline(always)]
+fn tt(addr: usize) -> u32 {
+    let resp: u32;
+    unsafe {
+        asm!("tt {resp}, {addr}", resp = lateout(reg) resp, addr = in(reg) addr, options(nomem, nostack, preserves_flags));
+    }
+    resp
+}
+
+#[inline(always)]
+fn ttt(addr: usize) -> u32 {
+    let resp: u32;
+    unsafe {
+        asm!("ttt {resp}, {addr}", resp = lateout(reg) resp, addr = in(reg) addr, options(nomem, nostack, preserves_flags));
+    }
+    resp
+}
+
+#[cfg(feature = "trustzone")]
+#[inline(always)]
+fn tta(addr: usize) -> u32 {
+    let resp: u32;
+    unsafe {
+        asm!("tta {resp}, {addr}", resp = lateout(reg) resp, addr = in(reg) addr, options(nomem, nostack, preserves_flags));
+    }
+    resp
+}
+
+#[cfg(feature = "trustzone")]
+#[inline(always)]
+fn ttat(addr: usize) -> u32 {
+    let resp: u32;
+    unsafe {
+        asm!("ttat {resp}, {addr}", resp = lateout(reg) resp, addr = in(reg) addr, options(nomem, nostack, preserves_flags));
+    }
+    resp
+}
+
+/// Whether the thread that trapped into the kernel runs unprivileged.
+/// CONTROL.nPRIV isn't touched by exception entry, so in handler mode it
+/// still describes the caller.
+#[inline(always)]
+fn caller_unprivileged() -> bool {
+    !cortex_m::register::control::read().npriv().is_privileged()
+}
+
+/// Check that the caller may access `[addr, addr + size)`, for writing too if
+/// `write` is set.  Both ends have to resolve to the same MPU region (or both
+/// to the privileged background map), which also rejects ranges that run off
+/// the end of the stack into a neighbouring region.
+pub fn check_user_range(addr: usize, size: usize, write: bool) -> Result<(), SyscallError> {
+    #[cfg(feature = "trustzone")]
+    if NONSECURE_CALLER.load(Ordering::Relaxed) {
+        return check_nonsecure_range(addr, size, write);
+    }
+    if size == 0 {
+        return Ok(());
+    }
+    if addr == 0 {
+        return Err(SyscallError::Fault);
+    }
+    let last = addr.checked_add(size - 1).ok_or(SyscallError::Fault)?;
+
+    let probe = if caller_unprivileged() { ttt } else { tt };
+    let first_resp = probe(addr);
+    let last_resp = probe(last);
+
+    let same_region = match (first_resp & TT_MRVALID != 0, last_resp & TT_MRVALID != 0) {
+        (true, true) => first_resp & TT_MREGION_MASK == last_resp & TT_MREGION_MASK,
+        (false, false) => true, // background map, only reachable privileged
+        _ => false,
+    };
+    if !same_region {
+        return Err(SyscallError::Fault);
+    }
+
+    let perm = if write { TT_RW } else { TT_R };
+    if first_resp & perm == 0 {
+        return Err(SyscallError::Fault);
+    }
+
+    Ok(())
+}
+
+/// Run `f`, normally the syscall dispatcher, with pointer checks done on
+/// behalf of a Non-secure caller.
+#[cfg(feature = "trustzone")]
+pub fn as_nonsecure_caller<R>(f: impl FnOnce() -> R) -> R {
+    NONSECURE_CALLER.store(true, Ordering::Relaxed);
+    let r = f();
+    NONSECURE_CALLER.store(false, Ordering::Relaxed);
+    r
+}
+
+/// Check that `[addr, addr + size)` is Non-secure memory the Non-secure
+/// caller may access, so it can't point the kernel at Secure state.  Both ends
+/// have to be in the same SAU region and the same Non-secure MPU region (or
+/// both in its background map), and the permission is the one an access at
+/// the caller's Non-secure privilege would get.
+#[cfg(feature = "trustzone")]
+pub fn check_nonsecure_range(addr: usize, size: usize, write: bool) -> Result<(), SyscallError> {
+    if size == 0 {
+        return Ok(());
+    }
+    if addr == 0 {
+        return Err(SyscallError::Fault);
+    }
+    let last = addr.checked_add(size - 1).ok_or(SyscallError::Fault)?;
+
+    let control_ns: u32;
+    unsafe {
+        asm!("mrs {}, CONTROL_NS", out(reg) control_ns, options(nomem, nostack, preserves_flags));
+    }
+    let probe = if control_ns & 1 != 0 { ttat } else { tta };
+    let first_resp = probe(addr);
+    let last_resp = probe(last);
+
+    if (first_resp | last_resp) & TT_S != 0 {
+        return Err(SyscallError::Fault);
+    }
+    let same_sau_region = first_resp & TT_SRVALID != 0 && last_resp & TT_SRVALID != 0
+        && (first_resp >> TT_SREGION_SHIFT) & 0xFF == (last_resp >> TT_SREGION_SHIFT) & 0xFF;
+    let same_mpu_region = match (first_resp & TT_MRVALID != 0, last_resp & TT_MRVALID != 0) {
+        (true, true) => first_resp & TT_MREGION_MASK == last_resp & TT_MREGION_MASK,
+        (false, false) => true,
+        _ => false,
+    };
+    if !same_sau_region || !same_mpu_region {
+        return Err(SyscallError::Fault);
+    }
+
+    let perm = if write { TT_NSRW } else { TT_NSR };
+    if first_resp & perm == 0 {
+        return Err(SyscallError::Fault);
+    }
+    Ok(())
+}
+
+/// A pointer to a single `T` in the caller's memory.
+#[derive(Copy, Clone)]
+#[repr(transparent)]
+pub struct UserPtr<T> {
+    addr: usize,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T> UserPtr<T> {
+    pub fn new(addr: usize) -> Self {
+        UserPtr { addr, _marker: PhantomData }
+    }
+
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+
+    fn check(&self, write: bool) -> Result<(), SyscallError> {
+        if !self.addr.is_multiple_of(align_of::<T>()) {
+            return Err(SyscallError::Fault);
+        }
+        check_user_range(self.addr, size_of::<T>(), write)
+    }
+
+    /// Copy the value out of user memory.
+    ///
+    /// # Safety
+    /// Only call from a syscall handler, on behalf of the thread that passed the pointer.
+    pub unsafe fn read(&self) -> Result<T, SyscallError> where T: Copy {
+        self.check(false)?;
+        Ok((self.addr as *const T).read_volatile())
+    }
+
+    /// Copy `value` into user memory.
+    ///
+    /// # Safety
+    /// Only call from a syscall handler, on behalf of the thread that passed the pointer.
+    pub unsafe fn write(&self, value: T) -> Result<(), SyscallError> {
+        self.check(true)?;
+        (self.addr as *mut T).write_volatile(value);
+        Ok(())
+    }
+}
+
+impl<T> SyscallArg for UserPtr<T> {
+    #[inline(always)]
+    fn into_reg(self) -> usize { self.addr }
+    #[inline(always)]
+    fn from_reg(reg: usize) -> Self { UserPtr::new(reg) }
+}
+
+/// `len` consecutive `T`s in the caller's memory.
+#[derive(Copy, Clone)]
+pub struct UserSlice<T> {
+    addr: usize,
+    len: usize,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T> UserSlice<T> {
+    pub fn new(addr: usize, len: usize) -> Self {
+        UserSlice { addr, len, _marker: PhantomData }
+    }
+
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn check(&self, write: bool) -> Result<(), SyscallError> {
+        if self.len == 0 {
+            return Ok(());
+        }
+        if !self.addr.is_multiple_of(align_of::<T>()) {
+            return Err(SyscallError::Fault);
+        }
+        let size = self.len.checked_mul(size_of::<T>()).ok_or(SyscallError::Fault)?;
+        check_user_range(self.addr, size, write)
+    }
+
+    /// Borrow the user buffer for reading.
+    ///
+    /// # Safety
+    /// Only call from a syscall handler, and drop the slice before returning
+    /// to thread mode; the caller owns the memory again after that.
+    pub unsafe fn as_slice<'a>(&self) -> Result<&'a [T], SyscallError> {
+        self.check(false)?;
+        if self.len == 0 {
+            return Ok(&[]);
+        }
+        Ok(core::slice::from_raw_parts(self.addr as *const T, self.len))
+    }
+
+    /// Borrow the user buffer for writing.
+    ///
+    /// # Safety
+    /// Same as [`UserSlice::as_slice`].
+    pub unsafe fn as_mut_slice<'a>(&self) -> Result<&'a mut [T], SyscallError> {
+        self.check(true)?;
+        if self.len == 0 {
+            return Ok(&mut []);
+        }
+        Ok(core::slice::from_raw_parts_mut(self.addr as *mut T, self.len))
+    }
+
+    /// Copy up to `dst.len()` elements out of user memory, returning how many were copied.
+    ///
+    /// # Safety
+    /// Same as [`UserSlice::as_slice`].
+    pub unsafe fn copy_to(&self, dst: &mut [T]) -> Result<usize, SyscallError> where T: Copy {
+        let src = self.as_slice()?;
+        let n = src.len().min(dst.len());
+        dst[..n].copy_from_slice(&src[..n]);
+        Ok(n)
+    }
+
+    /// Copy up to `self.len()` elements of `src` into user memory, returning how many were copied.
+    ///
+    /// # Safety
+    /// Same as [`UserSlice::as_slice`].
+    pub unsafe fn copy_from(&self, src: &[T]) -> Result<usize, SyscallError> where T: Copy {
+        let dst = self.as_mut_slice()?;
+        let n = src.len().min(dst.len());
+        dst[..n].copy_from_slice(&src[..n]);
+        Ok(n)
+    }
+}
@@ -0,0 +1,121 @@
+//! `wait_many`: block until the first of several things happens.
+
+use core::sync::atomic::AtomicU32;
+use crate::error::SyscallError;
+use crate::handle::Handle;
+use crate::user::UserPtr;
+
+/// Most conditions one `wait_many` call can wait on.
+pub const MAX_WAIT_SPECS: usize = 4;
+
+/// What a `WaitSpec` waits for.
+///
+/// Barriers aren't among them: waiting at one is an arrival, which can't be
+/// taken back when something else fires first.  Nor is room in a stream, as
+/// writers are served in order with their data in hand.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, defmt::Format)]
+#[repr(u32)]
+pub enum WaitKind {
+    /// Lock a mutex; the caller holds it if this one fires.
+    Mutex = 1,
+    /// Take a reader-writer lock shared, or exclusively.
+    RwRead = 2,
+    RwWrite = 3,
+    /// A `futex_wake` on the word, or the word not reading `expected`.
+    Futex = 4,
+    /// The thread exiting or being killed.
+    ThreadExit = 5,
+    /// A latch opening.
+    Latch = 6,
+    /// A stream filling up to its trigger level.  Nothing is read: follow
+    /// up with `stream_read`, which may still block if another reader got
+    /// there first.
+    StreamReadable = 7,
+    /// A notification for the caller, taken as by `notify_wait`.
+    Notify = 8,
+}
+
+impl WaitKind {
+    pub fn from_raw(raw: u32) -> Option<Self> {
+        Some(match raw {
+            1 => WaitKind::Mutex,
+            2 => WaitKind::RwRead,
+            3 => WaitKind::RwWrite,
+            4 => WaitKind::Futex,
+            5 => WaitKind::ThreadExit,
+            6 => WaitKind::Latch,
+            7 => WaitKind::StreamReadable,
+            8 => WaitKind::Notify,
+            _ => return None,
+        })
+    }
+}
+
+/// One condition for `wait_many`, as the kernel reads it from user memory.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct WaitSpec {
+    pub kind: u32,
+    /// Handle, the futex address, or where to put a notification.
+    pub target: u32,
+    /// Futex: the expected value.  Notify: the bits to clear once taken.
+    /// Unused otherwise.
+    pub arg: u32,
+}
+
+impl WaitSpec {
+    pub fn mutex(mutex: Handle) -> Self {
+        WaitSpec { kind: WaitKind::Mutex as u32, target: mutex.raw(), arg: 0 }
+    }
+
+    pub fn rwlock_read(lock: Handle) -> Self {
+        WaitSpec { kind: WaitKind::RwRead as u32, target: lock.raw(), arg: 0 }
+    }
+
+    pub fn rwlock_write(lock: Handle) -> Self {
+        WaitSpec { kind: WaitKind::RwWrite as u32, target: lock.raw(), arg: 0 }
+    }
+
+    pub fn futex(word: &AtomicU32, expected: u32) -> Self {
+        WaitSpec { kind: WaitKind::Futex as u32, target: word.as_ptr() as u32, arg: expected }
+    }
+
+    pub fn thread_exit(thread: Handle) -> Self {
+        WaitSpec { kind: WaitKind::ThreadExit as u32, target: thread.raw(), arg: 0 }
+    }
+
+    pub fn latch(latch: Handle) -> Self {
+        WaitSpec { kind: WaitKind::Latch as u32, target: latch.raw(), arg: 0 }
+    }
+
+    pub fn stream_readable(stream: Handle) -> Self {
+        WaitSpec { kind: WaitKind::StreamReadable as u32, target: stream.raw(), arg: 0 }
+    }
+
+    /// The notification word lands in `value`, which has to stay put until
+    /// `wait_many` returns; `clear_on_exit` as for `notify_wait`.
+    pub fn notify(value: &mut u32, clear_on_exit: u32) -> Self {
+        WaitSpec { kind: WaitKind::Notify as u32, target: value as *mut u32 as u32, arg: clear_on_exit }
+    }
+
+    pub fn kind(&self) -> Option<WaitKind> {
+        WaitKind::from_raw(self.kind)
+    }
+
+    pub fn handle(&self) -> Handle {
+        Handle::from_raw(self.target)
+    }
+}
+
+/// Block until the first of `specs` is satisfied, or `timeout_ms` passes
+/// (`TimedOut`), and return its index.  Whatever fired is acquired (a mutex
+/// is then held, say); the other conditions are dropped.  If several are
+/// already satisfied the lowest index wins.  With a timeout of 0 nothing
+/// blocks: `WouldBlock` if none is.
+pub fn wait_many(specs: &[WaitSpec], timeout_ms: u32) -> Result<usize, SyscallError> {
+    if specs.is_empty() || specs.len() > MAX_WAIT_SPECS {
+        return Err(SyscallError::InvalidArgument);
+    }
+    crate::wait_many_raw(UserPtr::new(specs.as_ptr() as usize), specs.len() as u32, timeout_ms)
+        .map(|index| index as usize)
+}
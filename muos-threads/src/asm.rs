@@ -6,25 +6,85 @@ use crate::thread::{Thread, ThreadContext};
 pub unsafe extern "C" fn do_context_switch(
     prev_ctx: *mut ThreadContext,   // r0
     next_ctx: *mut ThreadContext,   // r1
-    lr:       u32,                  // r2 = the EXC_RETURN
+    lr:       u32,                  // r2 = the EXC_RETURN of prev
 ) {
     naked_asm!(
-    // 1) grab the *old* PSP, push r4–r11, write it back
-    "    mrs    r3, PSP",         // r3 = old SP
+    // naked bodies get assembled on their own, without the target's FPU
+    ".fpu   fpv5-sp-d16",
+    // 1) grab the *old* PSP (the Non-secure one if EXC_RETURN.S is clear),
+    //    push s16–s31 if the frame is extended (EXC_RETURN bit 4 clear), push
+    //    r4–r11, write SP and EXC_RETURN back.  With lazy stacking the vstmdb
+    //    also forces the pending s0–s15 save into the frame.
+    "    tst    r2, #0x40",       // S == 0 -> thread ran Non-secure
+    "    ite    ne",
+    "    mrsne  r3, PSP",         // r3 = old SP
+    "    mrseq  r3, PSP_NS",
+    "    tst    r2, #0x10",       // FType == 0 -> thread has FP state
+    "    it     eq",
+    "    vstmdbeq r3!, {{s16-s31}}",
     "    stmdb  r3!, {{r4-r11}}", // push callee-saved
     "    str    r3, [r0]",        // prev_ctx->stack_addr = new SP
+    "    str    r2, [r0, #4]",    // prev_ctx->exc_return = EXC_RETURN
 
-    // 2) load the *new* thread’s SP (regs_start), pop its r4–r11, restore PSP
+    // 1b) CONTROL as the thread sees it: nPRIV is still the thread's, SPSEL
+    //     and FPCA read back as the handler's so rebuild them from EXC_RETURN
+    "    tst    r2, #0x40",
+    "    ite    ne",
+    "    mrsne  r12, CONTROL",
+    "    mrseq  r12, CONTROL_NS",
+    "    and    r12, r12, #1",    // keep nPRIV
+    "    tst    r2, #0x4",        // SPSEL: returned to PSP
+    "    it     ne",
+    "    orrne  r12, r12, #2",
+    "    tst    r2, #0x10",       // FPCA: extended frame
+    "    it     eq",
+    "    orreq  r12, r12, #4",
+    "    str    r12, [r0, #8]",   // prev_ctx->control
+    "    tst    r2, #0x40",
+    "    ite    ne",
+    "    mrsne  r12, PSPLIM",
+    "    mrseq  r12, PSPLIM_NS",
+    "    str    r12, [r0, #12]",  // prev_ctx->psplim
+
+    // 2) load the *new* thread’s SP (regs_start) and EXC_RETURN, pop its
+    //    r4–r11 (and s16–s31 for an extended frame)
     "    ldr    r3, [r1]",        // r3 = next_ctx->stack_addr
+    "    ldr    r2, [r1, #4]",    // r2 = next_ctx->exc_return
     "    ldmia  r3!, {{r4-r11}}", // pop callee-saved
+    "    tst    r2, #0x10",
+    "    it     eq",
+    "    vldmiaeq r3!, {{s16-s31}}",
+
+    // 2b) drop the old limit before moving PSP, then install the new one.
+    //     Only nPRIV of CONTROL is live in handler mode; SPSEL and FPCA are
+    //     set by the exception return from EXC_RETURN
+    "    mov    r12, #0",
+    "    tst    r2, #0x40",
+    "    beq    1f",
+    "    msr    PSPLIM, r12",
     "    msr    PSP, r3",         // PSP = the frame_start
+    "    ldr    r12, [r1, #12]",
+    "    msr    PSPLIM, r12",     // PSPLIM = next_ctx->psplim
+    "    ldr    r12, [r1, #8]",
+    "    and    r12, r12, #1",
+    "    msr    CONTROL, r12",    // CONTROL.nPRIV = next_ctx->control.nPRIV
+    "    b      2f",
+    // same for a Non-secure thread, on the NS banked registers
+    "1:  msr    PSPLIM_NS, r12",
+    "    msr    PSP_NS, r3",
+    "    ldr    r12, [r1, #12]",
+    "    msr    PSPLIM_NS, r12",
+    "    ldr    r12, [r1, #8]",
+    "    and    r12, r12, #1",
+    "    msr    CONTROL_NS, r12",
 
     // 3) make sure memory is coherent before EXC_RETURN
-    "    dsb",
+    "2:  dsb",
     "    isb",
 
-    // 4) exit the exception into thread-mode
+    // 4) exit the exception into thread-mode, unmasking what PendSV masked
     "    mov    lr, r2",          // LR = EXC_RETURN
+    "    cpsie  i",
     "    bx     lr",              // -> pops the HW exception-frame on PSP
     )
 }
@@ -35,13 +95,27 @@ pub unsafe extern "C" fn do_setup(
     psp:       u32,  // → r0
     control:   u32,  // → r1
     exc_return: u32, // → r2
+    psplim:    u32,  // → r3
 ) -> ! {
     naked_asm!(
-    // 1) set up our process‑stack pointer
+    // a Non-secure first thread (EXC_RETURN.S clear) uses the NS bank
+    "tst   r2, #0x40",
+    "beq   1f",
+
+    // 1) set up our process‑stack pointer and its limit
     "msr   PSP,  r0",
+    "msr   PSPLIM, r3",
 
     // 2) switch CONTROL (privilege/stack)
     "msr   CONTROL, r1",
+    "b     2f",
+
+    "1:",
+    "msr   PSP_NS, r0",
+    "msr   PSPLIM_NS, r3",
+    "msr   CONTROL_NS, r1",
+
+    "2:",
     "isb",
 
     // 3) prepare EXC_RETURN in LR
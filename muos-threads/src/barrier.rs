@@ -0,0 +1,109 @@
+//! Barriers: `parties` threads meet, then all go on together.
+//!
+//! Barriers are cyclic, the round starts over once everyone's through.  A
+//! waiter that times out leaves the round without holding up the next.  A
+//! thread that waited on a barrier (a `WouldBlock` poll doesn't count) is a
+//! member for good: once one exits the
+//! rest could never be complete again, so the barrier breaks and every wait,
+//! current or later, fails with `OwnerDead`.
+
+use muos_syscall::{Handle, SyscallError};
+use crate::object::{self, with_objects, Objects};
+use crate::scheduler::{with_scheduler, Scheduler, MAX_THREADS};
+use crate::thread::BlockReason;
+use crate::waitqueue::{WaitQueue, Waiter};
+
+pub(crate) struct KBarrier {
+    parties: u32,
+    arrived: u32,
+    members: u32, // bit per thread slot
+    broken: bool,
+    waiters: WaitQueue,
+}
+
+pub(crate) fn create(owner: usize, parties: u32) -> Result<Handle, SyscallError> {
+    if parties == 0 || parties as usize > MAX_THREADS {
+        return Err(SyscallError::InvalidArgument);
+    }
+    with_objects(|o| o.barriers.alloc(owner, KBarrier {
+        parties,
+        arrived: 0,
+        members: 0,
+        broken: false,
+        waiters: WaitQueue::new(),
+    }))
+}
+
+/// `true` if the caller completed the round, `false` if it was blocked until
+/// someone does.
+pub(crate) fn wait(handle: Handle, timeout_ms: Option<usize>) -> Result<bool, SyscallError> {
+    with_objects(|o| with_scheduler(|sched| {
+        let tid = sched.current_thread_id();
+        let b = o.barriers.get_mut(handle)?;
+        if b.broken {
+            return Err(SyscallError::OwnerDead);
+        }
+        if b.arrived + 1 < b.parties && timeout_ms == Some(0) {
+            return Err(SyscallError::WouldBlock);
+        }
+        // counted in now, so a member
+        b.members |= 1 << tid;
+        if b.arrived + 1 < b.parties {
+            b.arrived += 1;
+            b.waiters.block_current(sched, BlockReason::Object(handle), timeout_ms);
+            return Ok(false);
+        }
+        b.arrived = 0;
+        while let Some(w) = o.barriers.get_mut(handle)?.waiters.pop(sched) {
+            object::complete(o, sched, w, Ok(0));
+        }
+        Ok(true)
+    }))
+}
+
+pub(crate) fn queue(o: &mut Objects, handle: Handle) -> Result<&mut WaitQueue, SyscallError> {
+    Ok(&mut o.barriers.get_mut(handle)?.waiters)
+}
+
+/// Take `waiter` out of the current round.
+pub(crate) fn dequeue(o: &mut Objects, sched: &mut dyn Scheduler, handle: Handle, waiter: Waiter) -> bool {
+    let Ok(b) = o.barriers.get_mut(handle) else { return false };
+    let found = b.waiters.remove(sched, waiter);
+    if found {
+        b.arrived -= 1;
+    }
+    found
+}
+
+pub(crate) fn destroy(handle: Handle, caller: usize) -> Result<(), SyscallError> {
+    with_objects(|o| with_scheduler(|sched| {
+        if o.barriers.owner(handle)? != caller {
+            return Err(SyscallError::NotPermitted);
+        }
+        let mut b = o.barriers.free(handle)?;
+        while let Some(w) = b.waiters.pop(sched) {
+            object::complete(o, sched, w, Err(SyscallError::ObjectRemoved));
+        }
+        Ok(())
+    }))
+}
+
+/// Break every barrier `tid` was a member of.
+pub(crate) fn release_thread(tid: usize) {
+    with_objects(|o| with_scheduler(|sched| {
+        let mut broken = [None; crate::object::MAX_BARRIERS];
+        for (slot, (handle, b)) in broken.iter_mut().zip(o.barriers.iter_mut()) {
+            if b.members & (1 << tid) != 0 && !b.broken {
+                defmt::warn!("barrier {:#x}: member {} went away", handle.raw(), tid);
+                b.broken = true;
+                b.arrived = 0;
+                *slot = Some(handle);
+            }
+        }
+        for handle in broken.into_iter().flatten() {
+            while let Some(w) = o.barriers.get_mut(handle).ok().and_then(|b| b.waiters.pop(sched)) {
+                object::complete(o, sched, w, Err(SyscallError::OwnerDead));
+            }
+        }
+    }))
+}
@@ -0,0 +1,169 @@
+//! Reset reason and boot counter.
+//!
+//! A small block in `.uninit.persist` (see `memory.x`) survives everything
+//! but a power cycle.  Whoever is about to reset the system (a fault handler,
+//! the software watchdog, `system_reset`) writes the reason into it first;
+//! `init` picks it up on the next boot and `boot_info()` reports it.  A block
+//! failing its checksum means the RAM lost power, i.e. a power-on reset.
+
+use core::cell::RefCell;
+use core::mem::MaybeUninit;
+use cortex_m::interrupt::{self, Mutex};
+#[cfg(feature = "rp2350")]
+use rp235x_hal::pac;
+
+const PERSIST_MAGIC: u32 = 0x4D75_4F53; // "MuOS"
+
+#[derive(Copy, Clone, PartialEq, defmt::Format)]
+#[repr(u32)]
+pub enum ResetReason {
+    PowerOn = 0,
+    /// Software watchdog caught a stalled thread, or the hardware one bit.
+    Watchdog = 1,
+    /// `system_reset` syscall.
+    Software = 2,
+    /// HardFault or MemManage fault.
+    Fault = 3,
+    /// Reset pin, debugger, brown-out: nobody got to record anything.
+    Unknown = 4,
+}
+
+impl ResetReason {
+    fn from_raw(raw: u32) -> Option<Self> {
+        Some(match raw {
+            0 => ResetReason::PowerOn,
+            1 => ResetReason::Watchdog,
+            2 => ResetReason::Software,
+            3 => ResetReason::Fault,
+            4 => ResetReason::Unknown,
+            _ => return None,
+        })
+    }
+}
+
+/// Registers of the last fault, as the fault handler saw them.
+#[derive(Copy, Clone, Default, defmt::Format)]
+#[repr(C)]
+pub struct FaultSummary {
+    pub exception: u32, // 3 = HardFault, 4 = MemManage
+    pub pc: u32,
+    pub lr: u32,
+    pub cfsr: u32,
+    pub hfsr: u32,
+    pub addr: u32, // MMFAR or BFAR if valid, else 0
+}
+
+#[derive(Copy, Clone, defmt::Format)]
+pub struct BootInfo {
+    pub reason: ResetReason,
+    /// Watchdog: the stalled thread, or `u32::MAX` if the kernel stopped
+    /// feeding.  Software: the code passed to `system_reset`.  Fault: the
+    /// exception number.
+    pub detail: u32,
+    /// Boots since the last power-on, this one included.
+    pub boot_count: u32,
+    /// Most recent fault, even if later resets had other reasons.
+    pub last_fault: Option<FaultSummary>,
+}
+
+#[repr(C)]
+struct Persist {
+    magic: u32,
+    boot_count: u32,
+    reason: u32,
+    detail: u32,
+    has_fault: u32,
+    fault: FaultSummary,
+    checksum: u32,
+}
+
+impl Persist {
+    fn sum(&self) -> u32 {
+        let words = core::mem::size_of::<Persist>() / 4 - 1; // all but checksum
+        let p = self as *const Persist as *const u32;
+        (0..words).fold(PERSIST_MAGIC, |acc, i| acc.rotate_left(5) ^ unsafe { p.add(i).read_volatile() })
+    }
+
+    fn valid(&self) -> bool {
+        self.magic == PERSIST_MAGIC && self.checksum == self.sum()
+    }
+
+    fn seal(&mut self) {
+        self.checksum = self.sum();
+    }
+}
+
+#[link_section = ".uninit.persist"]
+static mut PERSIST: MaybeUninit<Persist> = MaybeUninit::uninit();
+
+static BOOT_INFO: Mutex<RefCell<Option<BootInfo>>> = Mutex::new(RefCell::new(None));
+
+/// Only ever touched with interrupts masked or from a fault handler that won't return.
+unsafe fn persist() -> &'static mut Persist {
+    // every bit pattern is a valid Persist
+    (*core::ptr::addr_of_mut!(PERSIST)).assume_init_mut()
+}
+
+/// Work out why we reset and bump the boot counter. Called once from `init`.
+pub(crate) fn init() {
+    interrupt::free(|cs| {
+        let p = unsafe { persist() };
+        #[cfg(feature = "rp2350")]
+        let hw_watchdog = unsafe { (*pac::WATCHDOG::ptr()).reason().read().timer().bit_is_set() };
+        #[cfg(not(feature = "rp2350"))]
+        let hw_watchdog = false;
+
+        let (reason, detail) = if !p.valid() {
+            *p = Persist { magic: PERSIST_MAGIC, boot_count: 0, reason: 0, detail: 0, has_fault: 0, fault: FaultSummary::default(), checksum: 0 };
+            (ResetReason::PowerOn, 0)
+        } else {
+            match ResetReason::from_raw(p.reason) {
+                Some(r) if r != ResetReason::Unknown => (r, p.detail),
+                _ if hw_watchdog => (ResetReason::Watchdog, u32::MAX),
+                _ => (ResetReason::Unknown, 0),
+            }
+        };
+
+        p.boot_count = p.boot_count.wrapping_add(1);
+        // anything that resets us without recording shows up as Unknown
+        p.reason = ResetReason::Unknown as u32;
+        p.detail = 0;
+        p.seal();
+
+        let info = BootInfo {
+            reason,
+            detail,
+            boot_count: p.boot_count,
+            last_fault: (p.has_fault != 0).then_some(p.fault),
+        };
+        defmt::info!("boot: {}", info);
+        *BOOT_INFO.borrow(cs).borrow_mut() = Some(info);
+    });
+}
+
+/// Why the system came up this time.
+pub fn boot_info() -> BootInfo {
+    interrupt::free(|cs| BOOT_INFO.borrow(cs).borrow().expect("boot info not initialized"))
+}
+
+/// Note the reason for the reset that's about to happen.
+pub(crate) fn record(reason: ResetReason, detail: u32) {
+    interrupt::free(|_| {
+        let p = unsafe { persist() };
+        p.reason = reason as u32;
+        p.detail = detail;
+        p.seal();
+    });
+}
+
+/// Like `record` for a fault, keeping its registers around.
+pub(crate) fn record_fault(fault: FaultSummary) {
+    interrupt::free(|_| {
+        let p = unsafe { persist() };
+        p.reason = ResetReason::Fault as u32;
+        p.detail = fault.exception;
+        p.has_fault = 1;
+        p.fault = fault;
+        p.seal();
+    });
+}
@@ -0,0 +1,91 @@
+//! Condition variables on top of kernel mutexes.
+//!
+//! `cond_wait` drops the mutex and blocks in one go, so a signal can't slip
+//! in between.  Whatever ends the wait (signal, timeout, destroy), the waiter
+//! is handed back to the mutex and only returns once it holds it again.
+
+use muos_syscall::{Handle, SyscallError};
+use crate::mutex;
+use crate::object::{with_objects, Objects};
+use crate::scheduler::{with_scheduler, Scheduler};
+use crate::thread::BlockReason;
+use crate::waitqueue::{WaitQueue, Waiter};
+
+pub(crate) struct KCond {
+    /// Mutex the current waiters use; rebound once nobody waits.
+    mutex: Option<Handle>,
+    waiters: WaitQueue,
+}
+
+pub(crate) fn create(owner: usize) -> Result<Handle, SyscallError> {
+    with_objects(|o| o.conds.alloc(owner, KCond { mutex: None, waiters: WaitQueue::new() }))
+}
+
+pub(crate) fn queue(o: &mut Objects, handle: Handle) -> Result<&mut WaitQueue, SyscallError> {
+    Ok(&mut o.conds.get_mut(handle)?.waiters)
+}
+
+/// Release `mutex` and block on `handle`. The caller always blocks on success.
+pub(crate) fn wait(handle: Handle, mutex: Handle, timeout_ms: Option<usize>) -> Result<(), SyscallError> {
+    with_objects(|o| with_scheduler(|sched| {
+        let tid = sched.current_thread_id();
+        let c = o.conds.get_mut(handle)?;
+        match c.mutex {
+            Some(m) if !c.waiters.is_empty() && m != mutex => return Err(SyscallError::InvalidArgument),
+            _ => c.mutex = Some(mutex),
+        }
+        mutex::unlock_in(o, sched, mutex, tid)?;
+        o.conds.get_mut(handle)?.waiters.block_current(sched, BlockReason::Object(handle), timeout_ms);
+        Ok(())
+    }))
+}
+
+// hand a cond waiter, already off the queue, back to its mutex
+fn requeue(o: &mut Objects, sched: &mut dyn Scheduler, handle: Handle, tid: usize, result: Result<(), SyscallError>) {
+    match o.conds.get_mut(handle).ok().and_then(|c| c.mutex) {
+        Some(m) => mutex::acquire_for(o, sched, m, tid, result),
+        None => sched.wake(tid, Err(SyscallError::ObjectRemoved)),
+    }
+}
+
+/// Wake the highest-priority waiter, or all of them. `true` if anyone woke.
+pub(crate) fn signal(handle: Handle, all: bool) -> Result<bool, SyscallError> {
+    with_objects(|o| with_scheduler(|sched| {
+        let mut woke = false;
+        while let Some((tid, _)) = o.conds.get_mut(handle)?.waiters.pop(sched) {
+            requeue(o, sched, handle, tid, Ok(()));
+            woke = true;
+            if !all {
+                break;
+            }
+        }
+        Ok(woke)
+    }))
+}
+
+/// Take `waiter` off the wait queue of `handle`.
+pub(crate) fn dequeue(o: &mut Objects, sched: &mut dyn Scheduler, handle: Handle, waiter: Waiter) -> bool {
+    o.conds.get_mut(handle).is_ok_and(|c| c.waiters.remove(sched, waiter))
+}
+
+pub(crate) fn destroy(handle: Handle, caller: usize) -> Result<(), SyscallError> {
+    with_objects(|o| with_scheduler(|sched| {
+        if o.conds.owner(handle)? != caller {
+            return Err(SyscallError::NotPermitted);
+        }
+        let mut c = o.conds.free(handle)?;
+        while let Some((tid, _)) = c.waiters.pop(sched) {
+            match c.mutex {
+                Some(m) => mutex::acquire_for(o, sched, m, tid, Err(SyscallError::ObjectRemoved)),
+                None => sched.wake(tid, Err(SyscallError::ObjectRemoved)),
+            }
+        }
+        Ok(())
+    }))
+}
+
+/// A `cond_wait` ran out of time and was dequeued: back to the mutex,
+/// reporting `TimedOut`.
+pub(crate) fn timed_out(o: &mut Objects, sched: &mut dyn Scheduler, handle: Handle, tid: usize) {
+    requeue(o, sched, handle, tid, Err(SyscallError::TimedOut));
+}
@@ -0,0 +1,94 @@
+//! Futexes: wait queues keyed by an address in the caller's memory.
+//!
+//! User space keeps the lock state in a `u32` it updates with atomics and
+//! only traps in when it has to sleep (`futex_wait`) or wake a sleeper
+//! (`futex_wake`).  The kernel never interprets the word; `futex_wait` just
+//! compares it to `expected` with interrupts masked, so a wake between the
+//! caller's last look and the syscall can't get lost.  A queue exists only
+//! while somebody waits on its address.
+
+use muos_syscall::{SyscallError, UserPtr};
+use crate::object::{self, with_objects, Objects};
+use crate::scheduler::{with_scheduler, Scheduler};
+use crate::thread::BlockReason;
+use crate::waitqueue::{WaitQueue, Waiter};
+
+/// Distinct addresses that can have waiters at the same time.
+pub(crate) const MAX_FUTEXES: usize = 8;
+
+pub(crate) struct Futex {
+    addr: usize,
+    waiters: WaitQueue,
+}
+
+/// `true` if the word at `addr` still reads `expected`, i.e. a wait on it
+/// would block.
+pub(crate) fn would_block(addr: UserPtr<u32>, expected: u32) -> Result<bool, SyscallError> {
+    Ok(unsafe { addr.read()? } == expected)
+}
+
+/// The queue for `addr`, set up if nobody waited on it yet.
+pub(crate) fn queue(o: &mut Objects, addr: usize) -> Result<&mut WaitQueue, SyscallError> {
+    let futexes = &mut o.futexes;
+    let slot = match futexes.iter().position(|f| matches!(f, Some(f) if f.addr == addr)) {
+        Some(i) => i,
+        None => {
+            let i = futexes.iter().position(Option::is_none).ok_or(SyscallError::NoMemory)?;
+            futexes[i] = Some(Futex { addr, waiters: WaitQueue::new() });
+            i
+        }
+    };
+    Ok(&mut futexes[slot].as_mut().unwrap().waiters)
+}
+
+/// Block the caller on `addr` if it still holds `expected`; `WouldBlock` if
+/// it doesn't.  The caller always blocks on success.
+pub(crate) fn wait(addr: UserPtr<u32>, expected: u32, timeout_ms: Option<usize>) -> Result<(), SyscallError> {
+    with_objects(|o| with_scheduler(|sched| {
+        if !would_block(addr, expected)? {
+            return Err(SyscallError::WouldBlock);
+        }
+        let key = addr.addr();
+        queue(o, key)?.block_current(sched, BlockReason::Futex(key), timeout_ms);
+        Ok(())
+    }))
+}
+
+/// Wake up to `count` threads waiting on `addr`, highest priority first.
+/// Returns how many woke.
+pub(crate) fn wake(addr: UserPtr<u32>, count: u32) -> Result<u32, SyscallError> {
+    // the word itself doesn't matter, but the caller has to be able to see it
+    unsafe { addr.read()? };
+    with_objects(|o| with_scheduler(|sched| {
+        let key = addr.addr();
+        let mut woken = 0;
+        while woken < count {
+            let Some(w) = pop(o, sched, key) else { break };
+            object::complete(o, sched, w, Ok(0));
+            woken += 1;
+        }
+        Ok(woken)
+    }))
+}
+
+// first waiter on `addr`, dropping the queue once it's empty
+fn pop(o: &mut Objects, sched: &mut dyn Scheduler, addr: usize) -> Option<Waiter> {
+    let slot = o.futexes.iter_mut().find(|f| matches!(f, Some(f) if f.addr == addr))?;
+    let futex = slot.as_mut().unwrap();
+    let w = futex.waiters.pop(sched);
+    if futex.waiters.is_empty() {
+        *slot = None;
+    }
+    w
+}
+
+/// Take `waiter` off the queue for `addr`, because it timed out or is going away.
+pub(crate) fn dequeue(o: &mut Objects, sched: &mut dyn Scheduler, addr: usize, waiter: Waiter) {
+    if let Some(slot) = o.futexes.iter_mut().find(|f| matches!(f, Some(f) if f.addr == addr)) {
+        let futex = slot.as_mut().unwrap();
+        futex.waiters.remove(sched, waiter);
+        if futex.waiters.is_empty() {
+            *slot = None;
+        }
+    }
+}
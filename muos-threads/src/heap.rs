@@ -0,0 +1,64 @@
+//! Per-thread heap regions for `muos-alloc`.
+//!
+//! Like the stacks, every thread slot owns one statically placed heap.  A
+//! thread spawned with `ThreadAttrs::heap` gets its slot's heap mapped
+//! user-RW through `HEAP_MPU_REGION` while it runs; nobody else (bar
+//! privileged code) can reach it.
+//!
+//! Where that is, `current()` tells: the kernel writes the running thread's
+//! heap to `HEAP_INFO` on every switch, and `HEAP_INFO_MPU_REGION` maps it
+//! read-only for everyone.  The board's memory.x places `.uninit.heap_info`
+//! (32 bytes) outside the kernel's SRAM region, which it may not overlap.
+
+use crate::scheduler::MAX_THREADS;
+
+pub const HEAP_SIZE: usize = 1024; // bytes
+
+/// MPU region the running thread's heap is mapped through.
+pub const HEAP_MPU_REGION: u8 = crate::memory::HEAP_REGION;
+
+/// MPU region `HEAP_INFO` is mapped through, read-only.
+pub const HEAP_INFO_MPU_REGION: u8 = crate::memory::HEAP_INFO_REGION;
+
+#[repr(align(32))] // MPU granule
+pub(crate) struct ThreadHeap {
+    pub(crate) heap: [u8; HEAP_SIZE],
+}
+
+#[link_section = ".uninit.heaps"]
+pub(crate) static mut THREAD_HEAPS: [ThreadHeap; MAX_THREADS] =
+    [const { ThreadHeap { heap: [0; HEAP_SIZE] } }; MAX_THREADS];
+
+#[repr(C, align(32))] // MPU granule
+pub(crate) struct HeapInfo {
+    pub(crate) base: usize,
+    pub(crate) size: usize, // 0: no heap
+}
+
+#[link_section = ".uninit.heap_info"]
+pub(crate) static mut HEAP_INFO: HeapInfo = HeapInfo { base: 0, size: 0 };
+
+/// (base, size) of the running thread's heap, or `None` if it has none.
+/// Works from unprivileged threads, without a syscall.
+pub fn current() -> Option<(usize, usize)> {
+    let info = unsafe { core::ptr::addr_of!(HEAP_INFO).read_volatile() };
+    (info.size != 0).then_some((info.base, info.size))
+}
+
+/// (base, size) of the heap belonging to thread slot `slot`.
+pub fn heap_slot(slot: usize) -> (usize, usize) {
+    let base = unsafe { core::ptr::addr_of!(THREAD_HEAPS[slot].heap) as usize };
+    (base, HEAP_SIZE)
+}
+
+/// Number of heap slots, one per thread slot.
+pub const fn heap_slots() -> usize {
+    MAX_THREADS
+}
+
+/// Forget whatever the previous user of `slot` left behind; the allocator
+/// sees a zero header word and starts over.
+pub(crate) fn reset_heap(slot: usize) {
+    let (base, _) = heap_slot(slot);
+    unsafe { (base as *mut u32).write_volatile(0) };
+}
@@ -8,14 +8,20 @@ use defmt;
 use crate::scheduler::{schedule, with_scheduler};
 
 use crate::{scheduler, thread};
-use crate::memory::mpu_program_thread;
+use crate::memory::{mpu_program_heap, mpu_program_thread};
 use crate::stack::STACK_SIZE;
 use crate::thread::ThreadContext;
+use crate::bootinfo::{record_fault, FaultSummary};
 
 #[exception]
 fn SysTick() {
-    with_scheduler(|sched| sched.systick());
-    cortex_m::peripheral::SCB::set_pendsv();
+    let mut resched = with_scheduler(|sched| sched.systick());
+    resched |= crate::object::expire_timeouts();
+    crate::watchdog::tick(crate::SYSTICK_FREQ_MS as usize);
+    // only when a slice ran out or someone woke up
+    if resched {
+        cortex_m::peripheral::SCB::set_pendsv();
+    }
 }
 
 #[exception]
@@ -44,7 +50,15 @@ unsafe fn HardFault(ef: &ExceptionFrame) -> ! {
     \n  BFARVALID={} @ {:#010X}",
     ef.r0(), ef.r1(), ef.r2(), ef.r3(), ef.r12(), ef.pc(), ef.lr(), ef.xpsr(), hfsr, cfsr, mmfar_valid, mmfar, bfar_valid,  bfar);
 
-    loop { }
+    record_fault(FaultSummary {
+        exception: 3,
+        pc: ef.pc(),
+        lr: ef.lr(),
+        cfsr,
+        hfsr,
+        addr: if mmfar_valid { mmfar } else if bfar_valid { bfar } else { 0 },
+    });
+    SCB::sys_reset()
 }
 
 #[exception]
@@ -64,13 +78,18 @@ unsafe fn MemoryManagement() -> ! {
     defmt::error!(
       "MemManage Fault!: {:#x} {}", mmar, mmar_valid
     );
-    loop { /* lock up or reset the thread */ }
+    // no exception frame handed to us here, so no pc/lr
+    record_fault(FaultSummary {
+        exception: 4,
+        cfsr,
+        addr: if mmar_valid { mmar } else { 0 },
+        ..FaultSummary::default()
+    });
+    SCB::sys_reset()
 }
 
-#[inline(always)]
-pub unsafe fn handle_pend_sv() {
-    defmt::trace!("handle_pend_sv");
-    let exc_return = 0xFFFF_FFFD;
+pub unsafe extern "C" fn handle_pend_sv(exc_return: u32) {
+    defmt::trace!("handle_pend_sv: EXC_RETURN={:#x}", exc_return);
 
     let maybe_ptrs: Option<(*mut ThreadContext, *mut ThreadContext)> =
         with_scheduler(|sched|
@@ -82,10 +101,11 @@ pub unsafe fn handle_pend_sv() {
         defmt::trace!("run do_context_switch with following: prev: {:#x} next: {:#x}", (*prev_ptr).stack_addr, (*next_ptr).stack_addr);
 
         // setup MPU for the new thread
-        let (stack_base, stack_size) = with_scheduler(|sched|
-            sched.get_current_thread_stack()
+        let ((stack_base, stack_size), heap) = with_scheduler(|sched|
+            (sched.get_current_thread_stack(), sched.get_current_thread_heap())
         );
         mpu_program_thread(stack_base, stack_size);
+        mpu_program_heap(heap);
 
         do_context_switch(prev_ptr, next_ptr, exc_return);
     }
@@ -97,10 +117,17 @@ pub unsafe fn handle_pend_sv() {
 #[no_mangle]
 pub unsafe extern "C" fn PendSV() -> ! {
     naked_asm!(
+    // mask interrupts until the switch is done: an ISR waking a thread must
+    // not see a context that's been picked but not saved yet.
+    // do_context_switch unmasks right before its exception return.
+    "cpsid  i",
+    // hand the real EXC_RETURN to the handler so an FP frame is detected
+    "mov    r0, lr",
+    "push   {{r4, lr}}",   // r4 only keeps MSP 8-byte aligned
     "bl     {handler}",
-    "ldr    lr, ={exc_ret}",
-    "bx     lr",
+    // no switch happened: return to the same thread the way we came in
+    "cpsie  i",
+    "pop    {{r4, pc}}",
     handler = sym handle_pend_sv,
-    exc_ret = const 0xFFFF_FFFDu32,
     )
 }
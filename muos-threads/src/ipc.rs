@@ -0,0 +1,226 @@
+//! Synchronous IPC through endpoints, with priority donation.
+//!
+//! `ipc_call` and `ipc_recv` rendezvous on an endpoint: whichever side comes
+//! second finds the other on the endpoint's queue.  The message is copied
+//! from the client's buffer into the server's right then, and the client
+//! stays `Blocked(IpcReply)` until `ipc_reply` copies the answer back.
+//!
+//! Every buffer is checked against the MPU when its owner makes the syscall,
+//! while its regions are the ones programmed; the copy itself may happen
+//! later, from the other thread's syscall, but the owner is blocked in the
+//! kernel until then so the memory is still its own.
+//!
+//! A timeout on `ipc_call` covers the whole call: a client that runs out of
+//! time while a server holds its call is let go (`timed_out`), and the reply
+//! then finds nobody to answer.
+//!
+//! A server runs at the highest priority of its own and of the clients whose
+//! calls it holds, and passes that on if it's itself blocked calling another
+//! server.
+
+use muos_syscall::user::check_user_range;
+use muos_syscall::{Handle, IpcCall, SyscallError, UserPtr, MAX_IPC_MSG};
+use crate::object::{self, with_objects, Objects};
+use crate::scheduler::{with_scheduler, Scheduler, MAX_THREADS};
+use crate::thread::{BlockReason, ThreadState};
+use crate::waitqueue::{WaitQueue, Waiter};
+
+pub(crate) struct KEndpoint {
+    /// Clients whose call nobody picked up yet.
+    callers: WaitQueue,
+    /// Servers waiting for a call.
+    receivers: WaitQueue,
+}
+
+/// Buffers a thread handed in for the IPC it's blocked in.
+#[derive(Copy, Clone)]
+pub(crate) struct IpcState {
+    send: (usize, usize),  // (addr, len)
+    reply: (usize, usize), // (addr, capacity)
+    recv: (usize, usize),  // (addr, capacity)
+    recv_len: usize,       // where to store the received length
+    /// Client: the server holding its call.
+    server: Option<usize>,
+}
+
+impl IpcState {
+    pub(crate) const EMPTY: IpcState = IpcState { send: (0, 0), reply: (0, 0), recv: (0, 0), recv_len: 0, server: None };
+}
+
+pub(crate) fn create(owner: usize) -> Result<Handle, SyscallError> {
+    with_objects(|o| o.endpoints.alloc(owner, KEndpoint { callers: WaitQueue::new(), receivers: WaitQueue::new() }))
+}
+
+fn check_msg(addr: usize, len: usize, write: bool) -> Result<(), SyscallError> {
+    if len > MAX_IPC_MSG {
+        return Err(SyscallError::InvalidArgument);
+    }
+    check_user_range(addr, len, write)
+}
+
+// both buffers were checked when their threads trapped in
+fn copy(src: (usize, usize), dst: (usize, usize)) -> usize {
+    let n = src.1.min(dst.1);
+    if n > 0 {
+        unsafe { core::ptr::copy_nonoverlapping(src.0 as *const u8, dst.0 as *mut u8, n) };
+    }
+    n
+}
+
+// hand `client`'s message to `server`; the client then waits for the reply
+fn deliver(o: &mut Objects, sched: &mut dyn Scheduler, client: usize, server: usize) {
+    let n = copy(o.ipc[client].send, o.ipc[server].recv);
+    unsafe { (o.ipc[server].recv_len as *mut u32).write_volatile(n as u32) };
+    o.ipc[client].server = Some(server);
+    sched.table_mut().threads[client].as_mut().unwrap().state = ThreadState::Blocked(BlockReason::IpcReply);
+    update_donation(o, sched, server);
+}
+
+/// Send the caller's message on `handle`. The caller always blocks on success.
+pub(crate) fn call(handle: Handle, call: UserPtr<IpcCall>, timeout_ms: Option<usize>) -> Result<(), SyscallError> {
+    let c = unsafe { call.read()? };
+    let send = (c.msg as usize, c.msg_len as usize);
+    let reply = (c.reply as usize, c.reply_cap as usize);
+    check_msg(send.0, send.1, false)?;
+    check_msg(reply.0, reply.1.min(MAX_IPC_MSG), true)?;
+
+    with_objects(|o| with_scheduler(|sched| {
+        let tid = sched.current_thread_id();
+        let e = o.endpoints.get_mut(handle)?;
+        o.ipc[tid] = IpcState { send, reply, ..IpcState::EMPTY };
+        match e.receivers.pop(sched) {
+            Some((server, _)) => {
+                sched.block_current_timeout(BlockReason::IpcReply, timeout_ms);
+                deliver(o, sched, tid, server);
+                let client = sched.thread_handle(tid);
+                sched.wake(server, Ok(client.raw() as usize));
+            }
+            None => e.callers.block_current(sched, BlockReason::Object(handle), timeout_ms),
+        }
+        Ok(())
+    }))
+}
+
+/// Take the next call on `handle`: `Some(client)` right away, or `None` if
+/// the caller was blocked until one comes in.
+pub(crate) fn recv(handle: Handle, buf: UserPtr<u8>, cap: u32, len: UserPtr<u32>, timeout_ms: Option<usize>) -> Result<Option<Handle>, SyscallError> {
+    let cap = (cap as usize).min(MAX_IPC_MSG);
+    check_msg(buf.addr(), cap, true)?;
+    unsafe { len.write(0)? };
+
+    with_objects(|o| with_scheduler(|sched| {
+        let tid = sched.current_thread_id();
+        let e = o.endpoints.get_mut(handle)?;
+        o.ipc[tid] = IpcState { recv: (buf.addr(), cap), recv_len: len.addr(), ..IpcState::EMPTY };
+        match e.callers.pop(sched) {
+            Some((client, _)) => {
+                deliver(o, sched, client, tid);
+                Ok(Some(sched.thread_handle(client)))
+            }
+            None => {
+                e.receivers.block_current(sched, BlockReason::Object(handle), timeout_ms);
+                Ok(None)
+            }
+        }
+    }))
+}
+
+/// Answer the call `client` is blocked in; only the server holding it may.
+pub(crate) fn reply(client: Handle, msg: UserPtr<u8>, len: u32) -> Result<(), SyscallError> {
+    check_msg(msg.addr(), len as usize, false)?;
+    with_objects(|o| with_scheduler(|sched| {
+        let tid = sched.current_thread_id();
+        let c = sched.thread_id(client)?;
+        let waiting = sched.thread(c).is_some_and(|t| t.state == ThreadState::Blocked(BlockReason::IpcReply));
+        if !waiting || o.ipc[c].server != Some(tid) {
+            return Err(SyscallError::NotPermitted);
+        }
+        let n = copy((msg.addr(), len as usize), o.ipc[c].reply);
+        o.ipc[c].server = None;
+        sched.wake(c, Ok(n));
+        update_donation(o, sched, tid);
+        Ok(())
+    }))
+}
+
+/// `tid`'s call ran out of time while a server held it: let it go and take
+/// back what it donated.
+pub(crate) fn timed_out(o: &mut Objects, sched: &mut dyn Scheduler, tid: usize) {
+    let server = o.ipc[tid].server.take();
+    sched.wake(tid, Err(SyscallError::TimedOut));
+    if let Some(server) = server {
+        update_donation(o, sched, server);
+    }
+}
+
+/// Recompute `server`'s priority from the calls it holds, and pass a change
+/// on down a chain of nested calls.
+fn update_donation(o: &mut Objects, sched: &mut dyn Scheduler, mut server: usize) {
+    for _ in 0..MAX_THREADS {
+        let Some(t) = sched.thread(server) else { return };
+        let mut prio = t.base_prio;
+        for c in 0..MAX_THREADS {
+            if o.ipc[c].server == Some(server) {
+                if let Some(ct) = sched.thread(c).filter(|t| t.state == ThreadState::Blocked(BlockReason::IpcReply)) {
+                    prio = prio.max(ct.prio);
+                }
+            }
+        }
+        let t = sched.table_mut().threads[server].as_mut().unwrap();
+        if t.prio == prio {
+            return;
+        }
+        defmt::debug!("ipc: thread {} runs at {} (base {})", server, prio, t.base_prio);
+        t.prio = prio;
+        let state = t.state;
+        object::reposition(o, sched, server);
+
+        match (state, o.ipc[server].server) {
+            (ThreadState::Blocked(BlockReason::IpcReply), Some(next)) => server = next,
+            _ => return,
+        }
+    }
+}
+
+pub(crate) fn queue(o: &mut Objects, handle: Handle, receivers: bool) -> Result<&mut WaitQueue, SyscallError> {
+    let e = o.endpoints.get_mut(handle)?;
+    Ok(if receivers { &mut e.receivers } else { &mut e.callers })
+}
+
+/// Take `waiter` off the queues of `handle`.
+pub(crate) fn dequeue(o: &mut Objects, sched: &mut dyn Scheduler, handle: Handle, waiter: Waiter) -> bool {
+    let Ok(e) = o.endpoints.get_mut(handle) else { return false };
+    e.callers.remove(sched, waiter) || e.receivers.remove(sched, waiter)
+}
+
+pub(crate) fn destroy(handle: Handle, caller: usize) -> Result<(), SyscallError> {
+    with_objects(|o| with_scheduler(|sched| {
+        if o.endpoints.owner(handle)? != caller {
+            return Err(SyscallError::NotPermitted);
+        }
+        let mut e = o.endpoints.free(handle)?;
+        while let Some(w) = e.callers.pop(sched).or_else(|| e.receivers.pop(sched)) {
+            object::complete(o, sched, w, Err(SyscallError::ObjectRemoved));
+        }
+        Ok(())
+    }))
+}
+
+/// `tid` is going away: fail the calls it holds as a server, and give back
+/// what it donated as a client.
+pub(crate) fn release_thread(tid: usize) {
+    with_objects(|o| with_scheduler(|sched| {
+        for c in 0..MAX_THREADS {
+            if o.ipc[c].server == Some(tid) {
+                o.ipc[c].server = None;
+                if sched.thread(c).is_some_and(|t| t.state == ThreadState::Blocked(BlockReason::IpcReply)) {
+                    sched.wake(c, Err(SyscallError::NoSuchThread));
+                }
+            }
+        }
+        if let Some(server) = o.ipc[tid].server.take() {
+            update_donation(o, sched, server);
+        }
+        o.ipc[tid] = IpcState::EMPTY;
+    }))
+}
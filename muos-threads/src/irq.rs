@@ -0,0 +1,167 @@
+//! Routing NVIC interrupts to threads.
+//!
+//! The kernel owns every device vector nobody else claims: they all land in
+//! `DefaultHandler`.  A thread `irq_attach`es a line, then loops on
+//! `irq_wait`, which unmasks it and blocks.  When it fires the handler masks
+//! it again (the device is still asserting it, only the thread knows how to
+//! quiet it) and wakes the owner.  None of this touches the NVIC from thread
+//! mode, so driver threads can run unprivileged.
+//!
+//! Unprivileged threads may only attach lines a privileged one `irq_grant`ed
+//! them; lines with a handler of their own in the vector table belong to the
+//! kernel image and can't be attached or granted at all.  A wait that times
+//! out masks its line again; an interrupt after that stays pending and ends
+//! the next `irq_wait` right away.
+
+use core::cell::RefCell;
+use cortex_m::interrupt::{self, Mutex};
+use cortex_m::interrupt::InterruptNumber;
+use cortex_m::peripheral::{NVIC, SCB};
+use cortex_m_rt::exception;
+use muos_syscall::SyscallError;
+use crate::scheduler::{with_scheduler, Scheduler};
+use crate::thread::{BlockReason, ThreadState};
+
+pub(crate) const MAX_IRQS: usize = 64; // RP2350 has 52 lines
+
+#[derive(Copy, Clone)]
+struct Irq(u16);
+
+unsafe impl InterruptNumber for Irq {
+    fn number(self) -> u16 {
+        self.0
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Line {
+    owner: Option<usize>,
+    /// Unprivileged thread allowed to attach it.
+    granted: Option<usize>,
+}
+
+static IRQ_LINES: Mutex<RefCell<[Line; MAX_IRQS]>> =
+    Mutex::new(RefCell::new([Line { owner: None, granted: None }; MAX_IRQS]));
+
+extern "C" {
+    // the `DefaultHandler` below, under its link name
+    fn DefaultHandler();
+}
+
+/// Lines a thread may have: in range and routed to `DefaultHandler`.
+fn check_line(irqn: u16) -> Result<(), SyscallError> {
+    if irqn as usize >= MAX_IRQS {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let vectors = unsafe { (*SCB::PTR).vtor.read() } as *const usize;
+    let handler = unsafe { vectors.add(16 + irqn as usize).read_volatile() };
+    if handler & !1 != DefaultHandler as unsafe extern "C" fn() as usize & !1 {
+        return Err(SyscallError::NotPermitted);
+    }
+    Ok(())
+}
+
+/// Make `tid` the owner of `irqn`; unprivileged threads need a grant.
+pub(crate) fn attach(irqn: u16, tid: usize, privileged: bool) -> Result<(), SyscallError> {
+    check_line(irqn)?;
+    interrupt::free(|cs| {
+        let line = &mut IRQ_LINES.borrow(cs).borrow_mut()[irqn as usize];
+        if !privileged && line.granted != Some(tid) {
+            return Err(SyscallError::NotPermitted);
+        }
+        match line.owner {
+            Some(owner) if owner != tid => Err(SyscallError::Busy),
+            _ => {
+                NVIC::mask(Irq(irqn));
+                line.owner = Some(tid);
+                Ok(())
+            }
+        }
+    })
+}
+
+/// Let the unprivileged thread `tid` attach `irqn`, in place of any earlier
+/// grant.  For privileged callers only.
+pub(crate) fn grant(irqn: u16, tid: usize) -> Result<(), SyscallError> {
+    check_line(irqn)?;
+    interrupt::free(|cs| {
+        let line = &mut IRQ_LINES.borrow(cs).borrow_mut()[irqn as usize];
+        if line.owner.is_some_and(|owner| owner != tid) {
+            return Err(SyscallError::Busy);
+        }
+        line.granted = Some(tid);
+        Ok(())
+    })
+}
+
+/// Re-enable `irqn` and block the current thread, which must own it, until it
+/// fires or `timeout_ms` runs out.
+pub(crate) fn wait(irqn: u16, timeout_ms: Option<usize>) -> Result<(), SyscallError> {
+    if irqn as usize >= MAX_IRQS {
+        return Err(SyscallError::InvalidArgument);
+    }
+    interrupt::free(|cs| with_scheduler(|sched| {
+        let tid = sched.current_thread_id();
+        if IRQ_LINES.borrow(cs).borrow()[irqn as usize].owner != Some(tid) {
+            return Err(SyscallError::NotPermitted);
+        }
+        sched.block_current_timeout(BlockReason::Irq(irqn), timeout_ms);
+        // still pending from while we weren't looking? then it fires right
+        // after the unmask and wakes us straight away
+        unsafe { NVIC::unmask(Irq(irqn)) };
+        Ok(())
+    }))
+}
+
+/// `tid`'s wait on `irqn` ran out of time: mask the line again, so it
+/// doesn't fire for nobody.
+pub(crate) fn timed_out(sched: &mut dyn Scheduler, tid: usize, irqn: u16) {
+    NVIC::mask(Irq(irqn));
+    sched.wake(tid, Err(SyscallError::TimedOut));
+}
+
+/// Give up every line owned by or granted to `tid`.
+pub(crate) fn release_thread(tid: usize) {
+    interrupt::free(|cs| {
+        for (irqn, line) in IRQ_LINES.borrow(cs).borrow_mut().iter_mut().enumerate() {
+            if line.owner == Some(tid) {
+                NVIC::mask(Irq(irqn as u16));
+                line.owner = None;
+            }
+            if line.granted == Some(tid) {
+                line.granted = None;
+            }
+        }
+    });
+}
+
+#[exception]
+unsafe fn DefaultHandler(irqn: i16) {
+    if irqn < 0 {
+        panic!("unhandled exception {}", irqn);
+    }
+    let irqn = irqn as u16;
+    NVIC::mask(Irq(irqn));
+
+    let owner = interrupt::free(|cs| {
+        IRQ_LINES.borrow(cs).borrow().get(irqn as usize).and_then(|line| line.owner)
+    });
+    match owner {
+        Some(tid) => {
+            let woken = with_scheduler(|sched| {
+                let waiting = sched.thread(tid).is_some_and(|t| t.state == ThreadState::Blocked(BlockReason::Irq(irqn)));
+                if waiting {
+                    sched.wake(tid, Ok(0));
+                }
+                waiting
+            });
+            if woken {
+                SCB::set_pendsv();
+            } else {
+                // owner is busy: keep it latched for the next irq_wait
+                NVIC::pend(Irq(irqn));
+            }
+        }
+        None => defmt::warn!("IRQ {} fired with no owner, masked", irqn),
+    }
+}
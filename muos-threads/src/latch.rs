@@ -0,0 +1,101 @@
+//! One-shot latches: threads wait until a count runs down to zero, e.g. for
+//! an init step to finish.  Once open a latch stays open.
+//!
+//! If the latch's creator exits before it opened, the waiters get
+//! `OwnerDead` rather than waiting for good: create it from the thread that
+//! does (or oversees) the init.
+
+use muos_syscall::{Handle, SyscallError};
+use crate::object::{self, with_objects, Objects};
+use crate::scheduler::{with_scheduler, Scheduler};
+use crate::thread::BlockReason;
+use crate::waitqueue::{WaitQueue, Waiter};
+
+pub(crate) struct KLatch {
+    count: u32,
+    waiters: WaitQueue,
+}
+
+pub(crate) fn create(owner: usize, count: u32) -> Result<Handle, SyscallError> {
+    with_objects(|o| o.latches.alloc(owner, KLatch { count, waiters: WaitQueue::new() }))
+}
+
+fn open(o: &mut Objects, sched: &mut dyn Scheduler, handle: Handle, result: Result<usize, SyscallError>) {
+    while let Some(w) = o.latches.get_mut(handle).ok().and_then(|l| l.waiters.pop(sched)) {
+        object::complete(o, sched, w, result);
+    }
+}
+
+/// `true` if that opened the latch and woke someone.
+pub(crate) fn count_down(handle: Handle) -> Result<bool, SyscallError> {
+    with_objects(|o| with_scheduler(|sched| {
+        let l = o.latches.get_mut(handle)?;
+        if l.count == 0 {
+            return Ok(false);
+        }
+        l.count -= 1;
+        if l.count > 0 || l.waiters.is_empty() {
+            return Ok(false);
+        }
+        open(o, sched, handle, Ok(0));
+        Ok(true)
+    }))
+}
+
+/// Returns `true` if the caller was blocked until the latch opens.
+pub(crate) fn wait(handle: Handle, timeout_ms: Option<usize>) -> Result<bool, SyscallError> {
+    with_objects(|o| with_scheduler(|sched| {
+        let l = o.latches.get_mut(handle)?;
+        if l.count == 0 {
+            return Ok(false);
+        }
+        if timeout_ms == Some(0) {
+            return Err(SyscallError::WouldBlock);
+        }
+        l.waiters.block_current(sched, BlockReason::Object(handle), timeout_ms);
+        Ok(true)
+    }))
+}
+
+pub(crate) fn is_open(o: &mut Objects, handle: Handle) -> Result<bool, SyscallError> {
+    Ok(o.latches.get_mut(handle)?.count == 0)
+}
+
+pub(crate) fn queue(o: &mut Objects, handle: Handle) -> Result<&mut WaitQueue, SyscallError> {
+    Ok(&mut o.latches.get_mut(handle)?.waiters)
+}
+
+pub(crate) fn dequeue(o: &mut Objects, sched: &mut dyn Scheduler, handle: Handle, waiter: Waiter) -> bool {
+    let Ok(l) = o.latches.get_mut(handle) else { return false };
+    l.waiters.remove(sched, waiter)
+}
+
+pub(crate) fn destroy(handle: Handle, caller: usize) -> Result<(), SyscallError> {
+    with_objects(|o| with_scheduler(|sched| {
+        if o.latches.owner(handle)? != caller {
+            return Err(SyscallError::NotPermitted);
+        }
+        open(o, sched, handle, Err(SyscallError::ObjectRemoved));
+        o.latches.free(handle)?;
+        Ok(())
+    }))
+}
+
+/// `tid` is going away: fail the waits on the latches it created and never
+/// opened, before they get destroyed with it.
+pub(crate) fn release_thread(tid: usize) {
+    with_objects(|o| with_scheduler(|sched| {
+        let mut closed = [None; crate::object::MAX_LATCHES];
+        for (slot, (handle, l)) in closed.iter_mut().zip(o.latches.iter_mut()) {
+            if l.count > 0 && !l.waiters.is_empty() {
+                *slot = Some(handle);
+            }
+        }
+        for handle in closed.into_iter().flatten() {
+            if o.latches.owner(handle) == Ok(tid) {
+                defmt::warn!("latch {:#x}: creator {} went away", handle.raw(), tid);
+                open(o, sched, handle, Err(SyscallError::OwnerDead));
+            }
+        }
+    }))
+}
@@ -1,4 +1,5 @@
 #![feature(naked_functions, asm)]
+#![cfg_attr(feature = "trustzone", feature(cmse_nonsecure_entry))]
 #![no_std]
 #![no_main]
 
@@ -6,26 +7,52 @@
 pub mod thread;
 pub mod scheduler;
 pub mod interrupts;
+pub mod object;
 mod asm;
 mod stack;
 mod memory;
+mod mutex;
+mod cond;
+mod rwlock;
+mod waitqueue;
+mod futex;
+mod waitmany;
+mod barrier;
+mod latch;
+mod ipc;
+pub mod notify;
+pub mod stream;
+mod syscalls;
+pub mod bootinfo;
+mod irq;
+pub mod work;
+pub mod watchdog;
+#[cfg(feature = "trustzone")]
+pub mod secure;
+#[cfg(feature = "user-heaps")]
+pub mod heap;
 
 use cortex_m::peripheral::scb::SystemHandler;
-use muos_syscall::register;
-use muos_syscall::numbers::{SCHEDULER_BOOT, YIELD_NOW, EXIT_THREAD, SLEEP_MS};
-use crate::asm::{do_setup};
-use crate::memory::{mpu_init_static, mpu_program_thread};
+use crate::memory::mpu_init_static;
+
+pub use crate::bootinfo::boot_info;
 
 pub(crate) const SYSTICK_FREQ_MS: u32 = 10; // 10 ms ticks
 
+const FPCCR_ASPEN: u32 = 1 << 31; // set FPCA on first FP instruction, stack FP state on exception entry
+const FPCCR_LSPEN: u32 = 1 << 30; // lazy stacking: reserve the FP frame, only fill it if the handler touches FP
+
 // init the scheduler & install our syscall handlers
 pub fn init(clock_freq: u32, core_periph: &mut cortex_m::peripheral::Peripherals) {
     unsafe {
         core_periph.SCB.set_priority(SystemHandler::PendSV, 0xFF);
     }
 
+    bootinfo::init();
+    init_fpu(core_periph);
     init_systick(clock_freq, core_periph);
     scheduler::init_scheduler();
+    work::init_worker();
     install_syscalls();
 }
 
@@ -34,21 +61,21 @@ pub fn boot() {
         mpu_init_static();
 
         cortex_m::interrupt::enable();
-        muos_syscall::scheduler_boot();
+        let _ = muos_syscall::scheduler_boot();
     }
 }
 
 fn install_syscalls() {
-    const HANDLERS: &[(usize, unsafe extern "C" fn(usize, usize, usize))] = &[
-        (SCHEDULER_BOOT, boot_handler),
-        (YIELD_NOW, yield_handler),
-        (SLEEP_MS, sleep_ms_handler),
-        (EXIT_THREAD, exit_handler),
-    ];
+    muos_syscall::install::<syscalls::Kernel>();
+}
 
-    for &(id, handler) in HANDLERS {
-        register(id, handler);
+fn init_fpu(core_periph: &mut cortex_m::peripheral::Peripherals) {
+    core_periph.SCB.enable_fpu();
+    unsafe {
+        core_periph.FPU.fpccr.modify(|r| r | FPCCR_ASPEN | FPCCR_LSPEN);
     }
+    cortex_m::asm::dsb();
+    cortex_m::asm::isb();
 }
 
 fn init_systick(clock_freq: u32, core_periph: &mut cortex_m::peripheral::Peripherals) {
@@ -60,34 +87,3 @@ fn init_systick(clock_freq: u32, core_periph: &mut cortex_m::peripheral::Periphe
     syst.enable_interrupt();
     syst.enable_counter();
 }
-
-unsafe extern "C" fn boot_handler(_: usize, _: usize, _: usize) {
-    defmt::trace!("boot handler");
-    let (psp, ctrl, eret, stack_base, stack_size) =
-        scheduler::with_scheduler(|s| {
-            let (psp, ctrl, eret) = s.get_initial_thread_registers();
-            let (stack_base, stack_size) = s.get_current_thread_stack();
-
-            (psp, ctrl, eret, stack_base, stack_size)
-        });
-
-    mpu_program_thread(stack_base, stack_size);
-    do_setup(psp, ctrl, eret)
-}
-
-unsafe extern "C" fn yield_handler(_: usize, _: usize, _: usize) {
-    defmt::trace!("yield handler");
-    cortex_m::peripheral::SCB::set_pendsv();
-}
-
-unsafe extern "C" fn sleep_ms_handler(ms: usize, _: usize, _: usize) {
-    defmt::trace!("sleep_ms handler: {}", ms);
-    scheduler::with_scheduler(|sched| sched.syscall_sleep_ms(ms));
-    cortex_m::peripheral::SCB::set_pendsv();
-}
-
-unsafe extern "C" fn exit_handler(_: usize, _: usize, _: usize) {
-    defmt::trace!("exit handler");
-    scheduler::with_scheduler(|sched| sched.syscall_exit_thread());
-    cortex_m::peripheral::SCB::set_pendsv();
-}
@@ -5,7 +5,8 @@ const FLASH_BASE: usize = 0x1000_0000;
 const FLASH_SIZE: usize = 2 * 1024 * 1024;  // 2048 KiB
 
 const SRAM_BASE:  usize = 0x2000_0000;
-const SRAM_SIZE:  usize = 512 * 1024;       //  512 KiB
+// 504 KiB less PERSIST and HEAP_INFO at the top; above those are THREAD_STACKS
+const SRAM_SIZE:  usize = 504 * 1024 - 256 - 32;
 
 /// New: AP & XN are for RBAR, not RLAR
 const RBAR_AP_PRIV_RO_USER_RO: u32 = 0b11 << 1;   // Flash
@@ -77,3 +78,41 @@ pub unsafe fn mpu_program_thread(stack_addr: usize, stack_size: usize) {
     // reprogram region 2
     program_region(2, stack_addr, stack_size, RBAR_AP_PRIV_RW_USER_RW, true);
 }
+
+pub(crate) const HEAP_REGION: u8 = 4;
+pub(crate) const HEAP_INFO_REGION: u8 = 5;
+
+/// Map the thread's private heap through region 4, or unmap it if it has none,
+/// and publish where it is through region 5.
+pub unsafe fn mpu_program_heap(heap: Option<(usize, usize)>) {
+    match heap {
+        Some((base, size)) => program_region(HEAP_REGION, base, size, RBAR_AP_PRIV_RW_USER_RW, true),
+        None => disable_region(HEAP_REGION),
+    }
+    #[cfg(feature = "user-heaps")]
+    publish_heap(heap);
+}
+
+unsafe fn disable_region(region: u8) {
+    let mpu = &*MPU::PTR;
+    mpu.rnr.write(region as u32);
+    mpu.rlar.write(0);
+}
+
+/// Write `heap` to `HEAP_INFO` and map that read-only for everyone.  Read-only
+/// binds privileged code too, so the region is off while the kernel writes.
+#[cfg(feature = "user-heaps")]
+unsafe fn publish_heap(heap: Option<(usize, usize)>) {
+    use crate::heap::{HeapInfo, HEAP_INFO};
+
+    disable_region(HEAP_INFO_REGION);
+    cortex_m::asm::dsb();
+    cortex_m::asm::isb();
+
+    let info = core::ptr::addr_of_mut!(HEAP_INFO);
+    let (base, size) = heap.unwrap_or((0, 0));
+    info.write_volatile(HeapInfo { base, size });
+    cortex_m::asm::dsb();
+
+    program_region(HEAP_INFO_REGION, info as usize, core::mem::size_of::<HeapInfo>(), RBAR_AP_PRIV_RO_USER_RO, true);
+}
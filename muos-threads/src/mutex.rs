@@ -0,0 +1,124 @@
+//! Kernel mutexes: non-recursive, ownership is handed straight to the next
+//! waiter on unlock so a woken thread never has to retry.
+
+use muos_syscall::{Handle, SyscallError};
+use crate::object::{self, with_objects, Objects};
+use crate::scheduler::{with_scheduler, Scheduler};
+use crate::thread::BlockReason;
+use crate::waitqueue::{WaitQueue, Waiter};
+
+pub(crate) struct KMutex {
+    holder: Option<usize>,
+    waiters: WaitQueue,
+}
+
+pub(crate) fn create(owner: usize) -> Result<Handle, SyscallError> {
+    with_objects(|o| o.mutexes.alloc(owner, KMutex { holder: None, waiters: WaitQueue::new() }))
+}
+
+/// Returns `true` if the caller was blocked and has to wait for a handoff,
+/// or `TimedOut` after `timeout_ms`.
+pub(crate) fn lock(handle: Handle, timeout_ms: Option<usize>) -> Result<bool, SyscallError> {
+    with_objects(|o| with_scheduler(|sched| {
+        let tid = sched.current_thread_id();
+        if try_lock_for(o, handle, tid)? {
+            return Ok(false);
+        }
+        queue(o, handle)?.block_current(sched, BlockReason::Object(handle), timeout_ms);
+        Ok(true)
+    }))
+}
+
+/// Take `handle` for `tid` if it's free.
+pub(crate) fn try_lock_for(o: &mut Objects, handle: Handle, tid: usize) -> Result<bool, SyscallError> {
+    let m = o.mutexes.get_mut(handle)?;
+    match m.holder {
+        None => {
+            m.holder = Some(tid);
+            Ok(true)
+        }
+        Some(holder) if holder == tid => Err(SyscallError::Deadlock),
+        Some(_) => Ok(false),
+    }
+}
+
+pub(crate) fn queue(o: &mut Objects, handle: Handle) -> Result<&mut WaitQueue, SyscallError> {
+    Ok(&mut o.mutexes.get_mut(handle)?.waiters)
+}
+
+// hand the mutex to the first waiter, or leave it free
+fn hand_off(o: &mut Objects, sched: &mut dyn Scheduler, handle: Handle) {
+    let Ok(m) = o.mutexes.get_mut(handle) else { return };
+    let next = m.waiters.pop(sched);
+    m.holder = next.map(|(tid, _)| tid);
+    if let Some(w) = next {
+        object::complete(o, sched, w, Ok(0));
+    }
+}
+
+pub(crate) fn unlock(handle: Handle) -> Result<(), SyscallError> {
+    with_objects(|o| with_scheduler(|sched| {
+        let tid = sched.current_thread_id();
+        unlock_in(o, sched, handle, tid)
+    }))
+}
+
+/// `unlock` on behalf of `tid`, for callers already holding both locks.
+pub(crate) fn unlock_in(o: &mut Objects, sched: &mut dyn Scheduler, handle: Handle, tid: usize) -> Result<(), SyscallError> {
+    let m = o.mutexes.get_mut(handle)?;
+    if m.holder != Some(tid) {
+        return Err(SyscallError::NotPermitted);
+    }
+    m.holder = None;
+    hand_off(o, sched, handle);
+    Ok(())
+}
+
+/// Get `handle` for the blocked thread `tid`, which is on no queue: wake it
+/// with `result` if the mutex is free, otherwise queue it up for the handoff.
+/// A mutex that's gone wakes it with `ObjectRemoved`.
+pub(crate) fn acquire_for(o: &mut Objects, sched: &mut dyn Scheduler, handle: Handle, tid: usize, result: Result<(), SyscallError>) {
+    match o.mutexes.get_mut(handle) {
+        Ok(m) if m.holder.is_none() => {
+            m.holder = Some(tid);
+            sched.wake(tid, result.map(|_| 0));
+        }
+        Ok(m) => m.waiters.requeue(sched, tid, BlockReason::Object(handle), result.err()),
+        Err(_) => sched.wake(tid, Err(SyscallError::ObjectRemoved)),
+    }
+}
+
+/// Take `waiter` off the wait queue of `handle`.
+pub(crate) fn dequeue(o: &mut Objects, sched: &mut dyn Scheduler, handle: Handle, waiter: Waiter) -> bool {
+    o.mutexes.get_mut(handle).is_ok_and(|m| m.waiters.remove(sched, waiter))
+}
+
+pub(crate) fn destroy(handle: Handle, caller: usize) -> Result<(), SyscallError> {
+    with_objects(|o| with_scheduler(|sched| {
+        if o.mutexes.owner(handle)? != caller {
+            return Err(SyscallError::NotPermitted);
+        }
+        let mut m = o.mutexes.free(handle)?;
+        while let Some(w) = m.waiters.pop(sched) {
+            object::complete(o, sched, w, Err(SyscallError::ObjectRemoved));
+        }
+        Ok(())
+    }))
+}
+
+/// Unlock every mutex `tid` still holds, e.g. because it exited.
+pub(crate) fn release_held_by(tid: usize) {
+    with_objects(|o| with_scheduler(|sched| {
+        let mut held = [None; crate::object::MAX_MUTEXES];
+        for (slot, (handle, m)) in held.iter_mut().zip(o.mutexes.iter_mut()) {
+            if m.holder == Some(tid) {
+                defmt::warn!("mutex {:#x}: holder {} went away", handle.raw(), tid);
+                m.holder = None;
+                *slot = Some(handle);
+            }
+        }
+        for handle in held.into_iter().flatten() {
+            hand_off(o, sched, handle);
+        }
+    }))
+}
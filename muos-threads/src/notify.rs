@@ -0,0 +1,84 @@
+//! Per-thread notification words.
+//!
+//! Every thread has a 32-bit word and a pending flag in its `Thread`, so
+//! signalling one costs no allocation and no object: `notify` updates the
+//! word and, if the target is in `notify_wait`, hands it over and wakes it.
+//! A target in `wait_many` with a `WaitKind::Notify` spec sits on its own
+//! `Objects::notify_waiters` queue instead.
+
+use cortex_m::peripheral::SCB;
+use muos_syscall::{Handle, NotifyAction, SyscallError, UserPtr};
+use crate::object::{self, with_objects};
+use crate::scheduler::{with_scheduler, Scheduler};
+use crate::thread::{BlockReason, Thread, ThreadState};
+
+// give the word to a thread in notify_wait; `notify_out` was checked when
+// it made the call
+fn take(t: &mut Thread) {
+    unsafe { (t.notify_out as *mut u32).write_volatile(t.notify_value) };
+    t.notify_value &= !t.notify_clear_on_exit;
+    t.notify_pending = false;
+}
+
+/// Notify `thread`. Safe to call from any ISR or privileged thread.
+pub fn notify(thread: Handle, value: u32, action: NotifyAction) -> Result<(), SyscallError> {
+    let woke = with_objects(|o| with_scheduler(|sched| {
+        let tid = sched.thread_id(thread)?;
+        let t = sched.table_mut().threads[tid].as_mut().ok_or(SyscallError::NoSuchThread)?;
+        t.notify_value = match action {
+            NotifyAction::SetBits => t.notify_value | value,
+            NotifyAction::Increment => t.notify_value.wrapping_add(1),
+            NotifyAction::Overwrite => value,
+        };
+        t.notify_pending = true;
+        if t.state == ThreadState::Blocked(BlockReason::Notify) {
+            take(t);
+            sched.wake(tid, Ok(0));
+            return Ok(true);
+        }
+        let Some(w) = o.notify_waiters[tid].pop(sched) else { return Ok(false) };
+        take(sched.table_mut().threads[tid].as_mut().unwrap());
+        object::complete(o, sched, w, Ok(0));
+        Ok(true)
+    }))?;
+    if woke {
+        SCB::set_pendsv();
+    }
+    Ok(())
+}
+
+/// Take the caller's notification into `out`. `true` if the caller was
+/// blocked until one comes in.
+pub(crate) fn wait(clear_on_entry: u32, clear_on_exit: u32, out: UserPtr<u32>, timeout_ms: Option<usize>) -> Result<bool, SyscallError> {
+    unsafe { out.write(0)? };
+    with_scheduler(|sched| {
+        let tid = sched.current_thread_id();
+        let t = sched.table_mut().threads[tid].as_mut().unwrap();
+        t.notify_out = out.addr();
+        t.notify_clear_on_exit = clear_on_exit;
+        if t.notify_pending {
+            take(t);
+            return Ok(false);
+        }
+        t.notify_value &= !clear_on_entry;
+        if timeout_ms == Some(0) {
+            return Err(SyscallError::WouldBlock);
+        }
+        sched.block_current_timeout(BlockReason::Notify, timeout_ms);
+        Ok(true)
+    })
+}
+
+/// `wait_many`'s check of a notification wait: take one that's pending into
+/// `out`, or leave `out` for `notify` to hand it over.  `true` if taken.
+pub(crate) fn poll(sched: &mut dyn Scheduler, tid: usize, out: UserPtr<u32>, clear_on_exit: u32) -> Result<bool, SyscallError> {
+    unsafe { out.write(0)? };
+    let t = sched.table_mut().threads[tid].as_mut().unwrap();
+    t.notify_out = out.addr();
+    t.notify_clear_on_exit = clear_on_exit;
+    if t.notify_pending {
+        take(t);
+        return Ok(true);
+    }
+    Ok(false)
+}
@@ -0,0 +1,386 @@
+//! Kernel object allocator.
+//!
+//! Every object type gets a fixed-size slab sized by the constants below.
+//! Objects are named by `Handle`s carrying the slot's generation, which is
+//! bumped on free, so a stale handle is rejected instead of silently
+//! resolving to whatever reused the slot.  Objects belong to the thread that
+//! created them and are reclaimed when it exits.
+
+use core::cell::RefCell;
+use cortex_m::interrupt::{self, Mutex};
+use muos_syscall::{Handle, ObjectKind, SyscallError, SyscallResult, WaitKind, WaitSpec, MAX_WAIT_SPECS};
+use muos_syscall::handle::MAX_GENERATION;
+use crate::barrier::KBarrier;
+use crate::cond::KCond;
+use crate::futex::{Futex, MAX_FUTEXES};
+use crate::ipc::{IpcState, KEndpoint};
+use crate::latch::KLatch;
+use crate::mutex::KMutex;
+use crate::rwlock::KRwLock;
+use crate::stream::{KStream, StreamIo};
+use crate::scheduler::{with_scheduler, Scheduler, MAX_THREADS};
+use crate::thread::{BlockReason, ThreadState};
+use crate::waitqueue::{WaitQueue, Waiter};
+
+pub(crate) const MAX_MUTEXES: usize = 8;
+pub(crate) const MAX_CONDS: usize = 8;
+pub(crate) const MAX_RWLOCKS: usize = 4;
+pub(crate) const MAX_ENDPOINTS: usize = 4;
+pub(crate) const MAX_STREAMS: usize = 4;
+pub(crate) const MAX_BARRIERS: usize = 4;
+pub(crate) const MAX_LATCHES: usize = 4;
+
+/// Usage of one object slab, as reported by `object_stats()`.
+#[derive(Copy, Clone, defmt::Format)]
+pub struct SlabStats {
+    pub kind: ObjectKind,
+    pub capacity: usize,
+    pub in_use: usize,
+    pub high_water: usize,
+    /// Lookups with a handle whose object was already freed.
+    pub stale_lookups: u32,
+}
+
+struct Slot<T> {
+    generation: u16,
+    owner: usize,
+    value: Option<T>,
+}
+
+pub(crate) struct Slab<T, const N: usize> {
+    kind: ObjectKind,
+    slots: [Slot<T>; N],
+    in_use: usize,
+    high_water: usize,
+    stale_lookups: u32,
+}
+
+impl<T, const N: usize> Slab<T, N> {
+    const fn new(kind: ObjectKind) -> Self {
+        Slab {
+            kind,
+            slots: [const { Slot { generation: 1, owner: 0, value: None } }; N],
+            in_use: 0,
+            high_water: 0,
+            stale_lookups: 0,
+        }
+    }
+
+    pub(crate) fn alloc(&mut self, owner: usize, value: T) -> Result<Handle, SyscallError> {
+        let index = self.slots.iter().position(|s| s.value.is_none())
+            .ok_or(SyscallError::NoMemory)?;
+        let slot = &mut self.slots[index];
+        slot.owner = owner;
+        slot.value = Some(value);
+
+        self.in_use += 1;
+        self.high_water = self.high_water.max(self.in_use);
+        Ok(Handle::new(self.kind, index, slot.generation))
+    }
+
+    fn slot(&mut self, handle: Handle) -> Result<&mut Slot<T>, SyscallError> {
+        if handle.kind() != Some(self.kind) || handle.index() >= N {
+            return Err(SyscallError::InvalidArgument);
+        }
+        let slot = &mut self.slots[handle.index()];
+        if slot.value.is_none() || slot.generation != handle.generation() {
+            self.stale_lookups += 1;
+            defmt::warn!("object: stale handle {:#x}", handle.raw());
+            return Err(SyscallError::InvalidArgument);
+        }
+        Ok(&mut self.slots[handle.index()])
+    }
+
+    pub(crate) fn get_mut(&mut self, handle: Handle) -> Result<&mut T, SyscallError> {
+        Ok(self.slot(handle)?.value.as_mut().unwrap())
+    }
+
+    pub(crate) fn owner(&mut self, handle: Handle) -> Result<usize, SyscallError> {
+        Ok(self.slot(handle)?.owner)
+    }
+
+    pub(crate) fn free(&mut self, handle: Handle) -> Result<T, SyscallError> {
+        let slot = self.slot(handle)?;
+        let value = slot.value.take().unwrap();
+        slot.generation = if slot.generation >= MAX_GENERATION { 1 } else { slot.generation + 1 };
+        self.in_use -= 1;
+        Ok(value)
+    }
+
+    /// Live objects, with their handles.
+    pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = (Handle, &mut T)> {
+        let kind = self.kind;
+        self.slots.iter_mut().enumerate().filter_map(move |(i, s)| {
+            let generation = s.generation;
+            s.value.as_mut().map(|v| (Handle::new(kind, i, generation), v))
+        })
+    }
+
+    /// Handle of the first live object created by `owner`.
+    pub(crate) fn first_owned_by(&self, owner: usize) -> Option<Handle> {
+        self.slots.iter().enumerate()
+            .find(|(_, s)| s.value.is_some() && s.owner == owner)
+            .map(|(i, s)| Handle::new(self.kind, i, s.generation))
+    }
+
+    pub(crate) fn stats(&self) -> SlabStats {
+        SlabStats {
+            kind: self.kind,
+            capacity: N,
+            in_use: self.in_use,
+            high_water: self.high_water,
+            stale_lookups: self.stale_lookups,
+        }
+    }
+}
+
+/// One slab per object type, plus the wait state that isn't an object.
+pub(crate) struct Objects {
+    pub(crate) mutexes: Slab<KMutex, MAX_MUTEXES>,
+    pub(crate) conds: Slab<KCond, MAX_CONDS>,
+    pub(crate) rwlocks: Slab<KRwLock, MAX_RWLOCKS>,
+    pub(crate) endpoints: Slab<KEndpoint, MAX_ENDPOINTS>,
+    pub(crate) streams: Slab<KStream, MAX_STREAMS>,
+    pub(crate) barriers: Slab<KBarrier, MAX_BARRIERS>,
+    pub(crate) latches: Slab<KLatch, MAX_LATCHES>,
+    pub(crate) futexes: [Option<Futex>; MAX_FUTEXES],
+    /// `wait_many` waiters for each thread slot's exit.
+    pub(crate) exit_waiters: [WaitQueue; MAX_THREADS],
+    /// Each thread slot's own `wait_many` waits for a notification.
+    pub(crate) notify_waiters: [WaitQueue; MAX_THREADS],
+    /// What each thread blocked in `wait_many` waits for, by wait slot.
+    pub(crate) wait_specs: [[Option<WaitSpec>; MAX_WAIT_SPECS]; MAX_THREADS],
+    /// Buffers and server of each thread slot's IPC in progress.
+    pub(crate) ipc: [IpcState; MAX_THREADS],
+    /// Buffer of each thread slot blocked on a stream.
+    pub(crate) stream_io: [StreamIo; MAX_THREADS],
+}
+
+impl Objects {
+    const fn new() -> Self {
+        Objects {
+            mutexes: Slab::new(ObjectKind::Mutex),
+            conds: Slab::new(ObjectKind::Cond),
+            rwlocks: Slab::new(ObjectKind::RwLock),
+            endpoints: Slab::new(ObjectKind::Endpoint),
+            streams: Slab::new(ObjectKind::Stream),
+            barriers: Slab::new(ObjectKind::Barrier),
+            latches: Slab::new(ObjectKind::Latch),
+            futexes: [const { None }; MAX_FUTEXES],
+            exit_waiters: [const { WaitQueue::new() }; MAX_THREADS],
+            notify_waiters: [const { WaitQueue::new() }; MAX_THREADS],
+            wait_specs: [[None; MAX_WAIT_SPECS]; MAX_THREADS],
+            ipc: [IpcState::EMPTY; MAX_THREADS],
+            stream_io: [StreamIo::EMPTY; MAX_THREADS],
+        }
+    }
+}
+
+static OBJECTS: Mutex<RefCell<Objects>> = Mutex::new(RefCell::new(Objects::new()));
+
+/// Helper to access the global object tables safely.
+pub(crate) fn with_objects<F, R>(f: F) -> R
+    where
+        F: FnOnce(&mut Objects) -> R,
+{
+    interrupt::free(|cs| f(&mut OBJECTS.borrow(cs).borrow_mut()))
+}
+
+/// Per-type usage of the kernel object pools.
+pub fn object_stats() -> [SlabStats; 7] {
+    with_objects(|o| [
+        o.mutexes.stats(), o.conds.stats(), o.rwlocks.stats(), o.endpoints.stats(),
+        o.streams.stats(), o.barriers.stats(), o.latches.stats(),
+    ])
+}
+
+/// Destroy the object behind `handle`; only its creator may do that.
+pub(crate) fn destroy(handle: Handle, caller: usize) -> Result<(), SyscallError> {
+    match handle.kind() {
+        Some(ObjectKind::Mutex) => crate::mutex::destroy(handle, caller),
+        Some(ObjectKind::Cond) => crate::cond::destroy(handle, caller),
+        Some(ObjectKind::RwLock) => crate::rwlock::destroy(handle, caller),
+        Some(ObjectKind::Endpoint) => crate::ipc::destroy(handle, caller),
+        Some(ObjectKind::Stream) => crate::stream::destroy(handle, caller),
+        Some(ObjectKind::Barrier) => crate::barrier::destroy(handle, caller),
+        Some(ObjectKind::Latch) => crate::latch::destroy(handle, caller),
+        // threads go away through thread_kill
+        Some(ObjectKind::Thread) | None => Err(SyscallError::InvalidArgument),
+    }
+}
+
+/// Give back everything an exiting thread holds or created.
+pub(crate) fn release_thread(tid: usize) {
+    cancel_wait(tid);
+    with_objects(|o| with_scheduler(|sched| {
+        while let Some(w) = o.exit_waiters[tid].pop(sched) {
+            complete(o, sched, w, Ok(0));
+        }
+    }));
+    crate::mutex::release_held_by(tid);
+    crate::rwlock::release_held_by(tid);
+    crate::ipc::release_thread(tid);
+    crate::barrier::release_thread(tid);
+    crate::latch::release_thread(tid);
+
+    while let Some(h) = with_objects(|o| o.mutexes.first_owned_by(tid)) {
+        let _ = destroy(h, tid);
+    }
+    while let Some(h) = with_objects(|o| o.conds.first_owned_by(tid)) {
+        let _ = destroy(h, tid);
+    }
+    while let Some(h) = with_objects(|o| o.rwlocks.first_owned_by(tid)) {
+        let _ = destroy(h, tid);
+    }
+    while let Some(h) = with_objects(|o| o.endpoints.first_owned_by(tid)) {
+        let _ = destroy(h, tid);
+    }
+    while let Some(h) = with_objects(|o| o.streams.first_owned_by(tid)) {
+        let _ = destroy(h, tid);
+    }
+    while let Some(h) = with_objects(|o| o.barriers.first_owned_by(tid)) {
+        let _ = destroy(h, tid);
+    }
+    while let Some(h) = with_objects(|o| o.latches.first_owned_by(tid)) {
+        let _ = destroy(h, tid);
+    }
+}
+
+/// Take `waiter` off whatever wait queue of `handle` it's on.
+fn dequeue(o: &mut Objects, sched: &mut dyn Scheduler, handle: Handle, waiter: Waiter) -> bool {
+    match handle.kind() {
+        Some(ObjectKind::Mutex) => crate::mutex::dequeue(o, sched, handle, waiter),
+        Some(ObjectKind::Cond) => crate::cond::dequeue(o, sched, handle, waiter),
+        Some(ObjectKind::RwLock) => crate::rwlock::dequeue(o, sched, handle, waiter),
+        Some(ObjectKind::Endpoint) => crate::ipc::dequeue(o, sched, handle, waiter),
+        Some(ObjectKind::Stream) => crate::stream::dequeue(o, sched, handle, waiter),
+        Some(ObjectKind::Barrier) => crate::barrier::dequeue(o, sched, handle, waiter),
+        Some(ObjectKind::Latch) => crate::latch::dequeue(o, sched, handle, waiter),
+        Some(ObjectKind::Thread) | None => false,
+    }
+}
+
+/// Move a blocked thread whose priority changed to its new place in the
+/// queues it waits on.
+pub(crate) fn reposition(o: &mut Objects, sched: &mut dyn Scheduler, tid: usize) {
+    match sched.thread(tid).map(|t| t.state) {
+        Some(ThreadState::Blocked(BlockReason::Object(handle))) => {
+            let w = (tid, 0);
+            match handle.kind() {
+                Some(ObjectKind::Mutex) => { let _ = crate::mutex::queue(o, handle).map(|q| q.reposition(sched, w)); }
+                Some(ObjectKind::Cond) => { let _ = crate::cond::queue(o, handle).map(|q| q.reposition(sched, w)); }
+                Some(ObjectKind::RwLock) => for write in [false, true] {
+                    let _ = crate::rwlock::queue(o, handle, write).map(|q| q.reposition(sched, w));
+                },
+                Some(ObjectKind::Endpoint) => for receivers in [false, true] {
+                    let _ = crate::ipc::queue(o, handle, receivers).map(|q| q.reposition(sched, w));
+                },
+                Some(ObjectKind::Stream) => for writers in [false, true] {
+                    let _ = crate::stream::queue(o, handle, writers).map(|q| q.reposition(sched, w));
+                },
+                Some(ObjectKind::Barrier) => { let _ = crate::barrier::queue(o, handle).map(|q| q.reposition(sched, w)); }
+                Some(ObjectKind::Latch) => { let _ = crate::latch::queue(o, handle).map(|q| q.reposition(sched, w)); }
+                Some(ObjectKind::Thread) | None => {}
+            }
+        }
+        Some(ThreadState::Blocked(BlockReason::Futex(addr))) => {
+            let _ = crate::futex::queue(o, addr).map(|q| q.reposition(sched, (tid, 0)));
+        }
+        Some(ThreadState::Blocked(BlockReason::Many)) => {
+            for slot in 0..MAX_WAIT_SPECS {
+                if let Some(spec) = o.wait_specs[tid][slot] {
+                    let _ = crate::waitmany::queue(o, &spec, tid).map(|q| q.reposition(sched, (tid, slot)));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// take one wait of a `wait_many` waiter off its queue
+fn dequeue_spec(o: &mut Objects, sched: &mut dyn Scheduler, spec: WaitSpec, waiter: Waiter) {
+    match spec.kind() {
+        Some(WaitKind::Mutex | WaitKind::RwRead | WaitKind::RwWrite | WaitKind::Latch | WaitKind::StreamReadable) => {
+            dequeue(o, sched, spec.handle(), waiter);
+        }
+        Some(WaitKind::Futex) => crate::futex::dequeue(o, sched, spec.target as usize, waiter),
+        Some(WaitKind::ThreadExit) => {
+            if let Some(q) = o.exit_waiters.get_mut(spec.handle().index()) {
+                q.remove(sched, waiter);
+            }
+        }
+        Some(WaitKind::Notify) => { o.notify_waiters[waiter.0].remove(sched, waiter); }
+        None => {}
+    }
+}
+
+/// Take a `wait_many` waiter off all its queues but `except`'s.
+pub(crate) fn cancel_many(o: &mut Objects, sched: &mut dyn Scheduler, tid: usize, except: Option<usize>) {
+    for slot in 0..MAX_WAIT_SPECS {
+        // take() first: dequeuing may wake others, who must not see this one
+        if let Some(spec) = o.wait_specs[tid][slot].take() {
+            if Some(slot) != except {
+                dequeue_spec(o, sched, spec, (tid, slot));
+            }
+        }
+    }
+}
+
+/// End the wait `waiter` stands for, already off its queue, with `result`.
+/// A `wait_many` waiter also leaves its other queues and gets the index of
+/// the wait that fired.
+pub(crate) fn complete(o: &mut Objects, sched: &mut dyn Scheduler, (tid, slot): Waiter, result: SyscallResult) {
+    let many = sched.thread(tid).is_some_and(|t| t.state == ThreadState::Blocked(BlockReason::Many));
+    if many {
+        cancel_many(o, sched, tid, Some(slot));
+        sched.wake(tid, result.map(|_| slot));
+    } else {
+        sched.wake(tid, result);
+    }
+}
+
+/// Unhook a thread that's going away from whatever it waits on.
+fn cancel_wait(tid: usize) {
+    with_objects(|o| with_scheduler(|sched| {
+        match sched.thread(tid).map(|t| t.state) {
+            Some(ThreadState::Blocked(BlockReason::Object(handle))) => { dequeue(o, sched, handle, (tid, 0)); }
+            Some(ThreadState::Blocked(BlockReason::Futex(addr))) => crate::futex::dequeue(o, sched, addr, (tid, 0)),
+            Some(ThreadState::Blocked(BlockReason::Many)) => cancel_many(o, sched, tid, None),
+            _ => {}
+        }
+    }))
+}
+
+/// Time out queued waits past their deadline; the scheduler's tick leaves
+/// them to us since the waiter has to come off its queues. Runs from SysTick;
+/// `true` if anyone woke.
+pub(crate) fn expire_timeouts() -> bool {
+    with_objects(|o| with_scheduler(|sched| {
+        let mut woke = false;
+        while let Some((tid, reason)) = sched.expired_wait() {
+            match reason {
+                BlockReason::Object(handle) => {
+                    dequeue(o, sched, handle, (tid, 0));
+                    match handle.kind() {
+                        Some(ObjectKind::Cond) => crate::cond::timed_out(o, sched, handle, tid),
+                        Some(ObjectKind::Stream) => crate::stream::timed_out(o, sched, handle, tid),
+                        _ => sched.wake(tid, Err(SyscallError::TimedOut)),
+                    }
+                }
+                BlockReason::Futex(addr) => {
+                    crate::futex::dequeue(o, sched, addr, (tid, 0));
+                    sched.wake(tid, Err(SyscallError::TimedOut));
+                }
+                BlockReason::Many => {
+                    cancel_many(o, sched, tid, None);
+                    sched.wake(tid, Err(SyscallError::TimedOut));
+                }
+                BlockReason::IpcReply => crate::ipc::timed_out(o, sched, tid),
+                BlockReason::Irq(irqn) => crate::irq::timed_out(sched, tid, irqn),
+                _ => sched.wake(tid, Err(SyscallError::TimedOut)),
+            }
+            woke = true;
+        }
+        woke
+    }))
+}
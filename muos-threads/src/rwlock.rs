@@ -0,0 +1,150 @@
+//! Kernel reader-writer locks: any number of readers or one writer.
+//!
+//! Like mutexes the lock is handed straight to whoever is next, so a woken
+//! thread already holds it.  With `prefer_writer` a waiting writer keeps new
+//! readers out until it got its turn; otherwise readers get in whenever no
+//! writer holds the lock, and writers wait for a gap.  Neither side is
+//! recursive.
+
+use muos_syscall::{Handle, SyscallError};
+use crate::object::{self, with_objects, Objects};
+use crate::scheduler::{with_scheduler, Scheduler};
+use crate::thread::BlockReason;
+use crate::waitqueue::{WaitQueue, Waiter};
+
+pub(crate) struct KRwLock {
+    writer: Option<usize>,
+    readers: u32, // bit per thread slot
+    prefer_writer: bool,
+    waiting_readers: WaitQueue,
+    waiting_writers: WaitQueue,
+}
+
+impl KRwLock {
+    fn is_reader(&self, tid: usize) -> bool {
+        self.readers & (1 << tid) != 0
+    }
+}
+
+pub(crate) fn create(owner: usize, prefer_writer: bool) -> Result<Handle, SyscallError> {
+    with_objects(|o| o.rwlocks.alloc(owner, KRwLock {
+        writer: None,
+        readers: 0,
+        prefer_writer,
+        waiting_readers: WaitQueue::new(),
+        waiting_writers: WaitQueue::new(),
+    }))
+}
+
+/// Returns `true` if the caller was blocked and has to wait for a handoff.
+pub(crate) fn lock(handle: Handle, write: bool, timeout_ms: Option<usize>) -> Result<bool, SyscallError> {
+    with_objects(|o| with_scheduler(|sched| {
+        let tid = sched.current_thread_id();
+        if try_lock_for(o, handle, tid, write)? {
+            return Ok(false);
+        }
+        queue(o, handle, write)?.block_current(sched, BlockReason::Object(handle), timeout_ms);
+        Ok(true)
+    }))
+}
+
+/// Take `handle` for `tid` if nothing's in the way.
+pub(crate) fn try_lock_for(o: &mut Objects, handle: Handle, tid: usize, write: bool) -> Result<bool, SyscallError> {
+    let l = o.rwlocks.get_mut(handle)?;
+    if l.writer == Some(tid) || l.is_reader(tid) {
+        return Err(SyscallError::Deadlock);
+    }
+    let free = if write {
+        l.writer.is_none() && l.readers == 0
+    } else {
+        l.writer.is_none() && !(l.prefer_writer && !l.waiting_writers.is_empty())
+    };
+    if free {
+        if write { l.writer = Some(tid) } else { l.readers |= 1 << tid }
+    }
+    Ok(free)
+}
+
+pub(crate) fn queue(o: &mut Objects, handle: Handle, write: bool) -> Result<&mut WaitQueue, SyscallError> {
+    let l = o.rwlocks.get_mut(handle)?;
+    Ok(if write { &mut l.waiting_writers } else { &mut l.waiting_readers })
+}
+
+pub(crate) fn unlock(handle: Handle) -> Result<(), SyscallError> {
+    with_objects(|o| with_scheduler(|sched| {
+        let tid = sched.current_thread_id();
+        let l = o.rwlocks.get_mut(handle)?;
+        if l.writer == Some(tid) {
+            l.writer = None;
+        } else if l.is_reader(tid) {
+            l.readers &= !(1 << tid);
+        } else {
+            return Err(SyscallError::NotPermitted);
+        }
+        grant(o, sched, handle);
+        Ok(())
+    }))
+}
+
+/// Let in whoever may have the lock now.
+pub(crate) fn grant(o: &mut Objects, sched: &mut dyn Scheduler, handle: Handle) {
+    let Ok(l) = o.rwlocks.get_mut(handle) else { return };
+    if l.writer.is_some() {
+        return;
+    }
+    if !l.waiting_writers.is_empty() && (l.prefer_writer || l.waiting_readers.is_empty()) {
+        if l.readers == 0 {
+            let w = l.waiting_writers.pop(sched).unwrap();
+            l.writer = Some(w.0);
+            object::complete(o, sched, w, Ok(0));
+        }
+        return;
+    }
+    loop {
+        let Ok(l) = o.rwlocks.get_mut(handle) else { return };
+        let Some(w) = l.waiting_readers.pop(sched) else { return };
+        l.readers |= 1 << w.0;
+        object::complete(o, sched, w, Ok(0));
+    }
+}
+
+/// Take `waiter` off the wait queues of `handle`, letting in whoever it held up.
+pub(crate) fn dequeue(o: &mut Objects, sched: &mut dyn Scheduler, handle: Handle, waiter: Waiter) -> bool {
+    let Ok(l) = o.rwlocks.get_mut(handle) else { return false };
+    let found = l.waiting_readers.remove(sched, waiter) || l.waiting_writers.remove(sched, waiter);
+    if found {
+        grant(o, sched, handle);
+    }
+    found
+}
+
+pub(crate) fn destroy(handle: Handle, caller: usize) -> Result<(), SyscallError> {
+    with_objects(|o| with_scheduler(|sched| {
+        if o.rwlocks.owner(handle)? != caller {
+            return Err(SyscallError::NotPermitted);
+        }
+        let mut l = o.rwlocks.free(handle)?;
+        while let Some(w) = l.waiting_readers.pop(sched).or_else(|| l.waiting_writers.pop(sched)) {
+            object::complete(o, sched, w, Err(SyscallError::ObjectRemoved));
+        }
+        Ok(())
+    }))
+}
+
+/// Drop every read or write hold `tid` still has, e.g. because it exited.
+pub(crate) fn release_held_by(tid: usize) {
+    with_objects(|o| with_scheduler(|sched| {
+        let mut held = [None; crate::object::MAX_RWLOCKS];
+        for (slot, (handle, l)) in held.iter_mut().zip(o.rwlocks.iter_mut()) {
+            if l.writer == Some(tid) || l.is_reader(tid) {
+                defmt::warn!("rwlock {:#x}: holder {} went away", handle.raw(), tid);
+                l.writer = l.writer.filter(|&w| w != tid);
+                l.readers &= !(1 << tid);
+                *slot = Some(handle);
+            }
+        }
+        for handle in held.into_iter().flatten() {
+            grant(o, sched, handle);
+        }
+    }))
+}
@@ -0,0 +1,197 @@
+//! Earliest-deadline-first scheduling for periodic threads.
+//!
+//! A thread spawned with `ThreadAttrs::edf` set releases a job every
+//! `period_ms`; the job has to finish (`period_wait`) within `deadline_ms` of
+//! its release and may use at most `budget_ms` of CPU.  Among released jobs
+//! with budget left the one with the earliest absolute deadline runs.  A job
+//! that burns its budget is throttled until its next release.  Threads
+//! without EDF parameters run in the background, by fixed priority, whenever
+//! no job is runnable.
+//!
+//! Misses are counted per thread, see `edf_stats`.
+
+use muos_syscall::{Handle, SyscallError};
+use crate::SYSTICK_FREQ_MS;
+use crate::thread::{BlockReason, ThreadAttrs, ThreadContext, ThreadFn, ThreadState};
+use super::{with_active_scheduler, Scheduler, ThreadTable, MAX_THREADS};
+
+/// Timing of a periodic thread, all in milliseconds.
+#[derive(Copy, Clone, defmt::Format)]
+pub struct EdfParams {
+    pub period_ms: u32,
+    pub budget_ms: u32,
+    /// Relative to the release; usually `<= period_ms`.
+    pub deadline_ms: u32,
+}
+
+/// Per-thread EDF counters, as reported by `edf_stats()`.
+#[derive(Copy, Clone, Default, defmt::Format)]
+pub struct EdfStats {
+    pub jobs: u32,
+    pub deadline_misses: u32,
+    /// Jobs throttled for using up their budget.
+    pub budget_overruns: u32,
+}
+
+#[derive(Copy, Clone)]
+struct Job {
+    params: EdfParams,
+    next_release: usize,
+    abs_deadline: usize,
+    budget_left: usize,
+    active: bool, // released and not finished yet
+    missed: bool,
+    stats: EdfStats,
+}
+
+impl Job {
+    fn release(&mut self, now: usize) {
+        self.abs_deadline = now.wrapping_add(self.params.deadline_ms as usize);
+        self.budget_left = self.params.budget_ms as usize;
+        self.next_release = now.wrapping_add(self.params.period_ms as usize);
+        self.active = true;
+        self.missed = false;
+        self.stats.jobs += 1;
+    }
+
+    fn runnable(&self) -> bool {
+        self.active && self.budget_left > 0
+    }
+}
+
+// wrap-safe "a is before b" for tick timestamps
+fn before(a: usize, b: usize) -> bool {
+    (a.wrapping_sub(b) as isize) < 0
+}
+
+pub struct EdfScheduler {
+    table: ThreadTable,
+    jobs: [Option<Job>; MAX_THREADS],
+}
+
+impl EdfScheduler {
+    pub const fn new() -> Self {
+        EdfScheduler { table: ThreadTable::new(), jobs: [None; MAX_THREADS] }
+    }
+
+    /// Runnable job with the earliest deadline; the current thread counts if
+    /// it's still running.
+    fn earliest_job(&self) -> Option<usize> {
+        let curr = self.table.current_thread_id;
+        let mut best: Option<(usize, usize)> = None;
+        for (tid, job) in self.jobs.iter().enumerate() {
+            let Some(job) = job.filter(Job::runnable) else { continue };
+            let ready = match &self.table.threads[tid] {
+                Some(t) => !t.throttled && (t.state == ThreadState::Ready
+                    || (Some(tid) == curr && t.state == ThreadState::Running)),
+                None => false,
+            };
+            if ready && best.is_none_or(|(_, d)| before(job.abs_deadline, d)) {
+                best = Some((tid, job.abs_deadline));
+            }
+        }
+        best.map(|(tid, _)| tid)
+    }
+}
+
+impl Default for EdfScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler for EdfScheduler {
+    fn table(&self) -> &ThreadTable {
+        &self.table
+    }
+
+    fn table_mut(&mut self) -> &mut ThreadTable {
+        &mut self.table
+    }
+
+    fn spawn(&mut self, thread_fn: ThreadFn, attrs: ThreadAttrs) -> Handle {
+        let (slot, handle) = self.table.spawn(thread_fn, attrs);
+        self.jobs[slot] = attrs.edf.map(|params| {
+            let mut job = Job {
+                params,
+                next_release: 0,
+                abs_deadline: 0,
+                budget_left: 0,
+                active: false,
+                missed: false,
+                stats: EdfStats::default(),
+            };
+            job.release(self.table.now());
+            job
+        });
+        handle
+    }
+
+    fn schedule(&mut self) -> Option<(*mut ThreadContext, *mut ThreadContext)> {
+        let curr = self.table.current_thread_id.expect("No current thread");
+        match self.earliest_job() {
+            Some(tid) if tid == curr => {
+                // may have been woken before the switch away from it ran
+                self.table.threads[curr].as_mut().unwrap().state = ThreadState::Running;
+                None
+            }
+            Some(tid) => self.table.do_switch(curr, tid),
+            // nothing periodic to run: background threads
+            None => {
+                let jobs = &self.jobs;
+                self.table.schedule_by_prio(|tid| jobs[tid].is_none())
+            }
+        }
+    }
+
+    fn systick(&mut self) -> bool {
+        self.table.tick();
+        let now = self.table.now();
+        let curr = self.table.current_thread_id;
+
+        for tid in 0..MAX_THREADS {
+            let Some(job) = self.jobs[tid].as_mut() else { continue };
+            let Some(thread) = self.table.threads[tid].as_mut() else {
+                // slot freed: the thread exited or was killed
+                self.jobs[tid] = None;
+                continue;
+            };
+
+            if Some(tid) == curr && thread.state == ThreadState::Running && job.runnable() {
+                job.budget_left = job.budget_left.saturating_sub(SYSTICK_FREQ_MS as usize);
+                if job.budget_left == 0 {
+                    job.stats.budget_overruns += 1;
+                    defmt::warn!("edf: thread {} used up its budget", tid);
+                }
+            }
+
+            if job.active && !job.missed && !before(now, job.abs_deadline) {
+                job.missed = true;
+                job.stats.deadline_misses += 1;
+                defmt::warn!("edf: thread {} missed its deadline", tid);
+            }
+
+            if !before(now, job.next_release) {
+                job.release(now);
+                if thread.state == ThreadState::Blocked(BlockReason::NextPeriod) {
+                    thread.make_ready();
+                }
+            }
+        }
+        // releases, budgets and deadlines move every tick; let schedule sort it out
+        true
+    }
+
+    fn wait_next_period(&mut self) -> Result<(), SyscallError> {
+        let tid = self.current_thread_id();
+        let job = self.jobs[tid].as_mut().ok_or(SyscallError::InvalidArgument)?;
+        job.active = false;
+        self.block_current(BlockReason::NextPeriod);
+        Ok(())
+    }
+}
+
+/// EDF counters of every periodic thread, indexed by thread slot.
+pub fn edf_stats() -> [Option<EdfStats>; MAX_THREADS] {
+    with_active_scheduler(|s| s.jobs.map(|j| j.map(|j| j.stats)))
+}
@@ -0,0 +1,620 @@
+#![no_std]
+
+use core::arch::asm;
+use core::cell::RefCell;
+use cortex_m::interrupt::{self, Mutex};
+use muos_syscall::{Handle, ObjectKind, SyscallError, SyscallResult};
+use muos_syscall::handle::MAX_GENERATION;
+use crate::{asm, SYSTICK_FREQ_MS};
+use crate::stack::{stack_base, STACK_SIZE};
+
+use crate::thread::{ThreadState, Thread, ThreadFn, ThreadContext, ThreadAttrs, BlockReason, DEFAULT_TIME_SLICE_MS, EXC_RETURN_S};
+
+mod rr;
+#[cfg(feature = "edf")]
+pub mod edf;
+
+pub use rr::RRScheduler;
+#[cfg(feature = "edf")]
+pub use edf::EdfScheduler;
+
+pub(crate) const MAX_THREADS: usize = 8;
+/// Priorities that can have their own default time slice.
+pub const MAX_PRIO_TIME_SLICES: usize = 8;
+/// Longest sleep or timeout, in ms: the tick clock wraps, so deadlines are
+/// only ordered within half its range.  Longer timeouts wait forever.
+pub(crate) const MAX_TIMEOUT_MS: usize = isize::MAX as usize;
+
+// wrap-safe "the clock has reached deadline"
+fn due(now: usize, deadline: usize) -> bool {
+    (now.wrapping_sub(deadline) as isize) >= 0
+}
+
+/// The scheduler the kernel runs, picked at compile time.
+#[cfg(not(feature = "edf"))]
+pub type ActiveScheduler = RRScheduler;
+#[cfg(feature = "edf")]
+pub type ActiveScheduler = EdfScheduler;
+
+/// A scheduling policy. Implementations own a `ThreadTable` and decide who
+/// runs next; thread bookkeeping is shared through the provided methods.
+pub trait Scheduler {
+    fn table(&self) -> &ThreadTable;
+    fn table_mut(&mut self) -> &mut ThreadTable;
+
+    fn spawn(&mut self, thread_fn: ThreadFn, attrs: ThreadAttrs) -> Handle;
+    fn schedule(&mut self) -> Option<(*mut ThreadContext, *mut ThreadContext)>;
+    /// Account one tick; `true` if `schedule` has something to decide.
+    fn systick(&mut self) -> bool;
+
+    /// End the current thread's job and block until its next period.
+    /// Only meaningful for policies with periodic threads.
+    fn wait_next_period(&mut self) -> Result<(), SyscallError> {
+        Err(SyscallError::InvalidArgument)
+    }
+
+    fn spawn_idle(&mut self, thread_fn: ThreadFn) {
+        self.table_mut().spawn_idle(thread_fn);
+    }
+
+    /// Give up the rest of the current thread's time slice.
+    fn yield_current(&mut self) {
+        let t = self.table_mut();
+        let tid = t.current_thread_id.unwrap();
+        t.threads[tid].as_mut().unwrap().quantum_left = 0;
+    }
+
+    fn get_initial_thread_registers(&mut self) -> (u32, u32, u32, u32) {
+        let t = self.table();
+        let tid = t.current_thread_id.unwrap();
+        let thread = t.threads[tid].as_ref().unwrap();
+
+        // skip the callee-saved block so psp points at the hardware exception frame:
+        let psp        = thread.context.stack_addr + thread.context.callee_frame_size();
+        let control    = thread.get_ctrl();
+        let exc_return = thread.context.exc_return;
+        let psplim     = thread.context.psplim;
+
+        //defmt::debug!(
+        //    "booting: tid={}  psp={:#010x}  ctrl={:#x}  EXC_RETURN={:#x}",
+        //    tid, psp, control, exc_return
+        //  );
+
+        (psp, control, exc_return, psplim)
+    }
+
+    fn get_current_thread_stack(&self) -> (usize, usize) { // (stack base, stack size)
+        let t = self.table();
+        let tid = t.current_thread_id.unwrap();
+        let nonsecure = t.threads[tid].as_ref().unwrap().context.exc_return & EXC_RETURN_S == 0;
+
+        defmt::trace!("get stack - tid: {}", tid);
+        (stack_base(tid, nonsecure) as usize, STACK_SIZE as usize)
+    }
+
+    fn get_current_thread_heap(&self) -> Option<(usize, usize)> {
+        #[cfg(feature = "user-heaps")]
+        {
+            let t = self.table();
+            let tid = t.current_thread_id.unwrap();
+            if t.threads[tid].as_ref().unwrap().heap {
+                return Some(crate::heap::heap_slot(tid));
+            }
+        }
+        None
+    }
+
+    fn syscall_sleep_ms(&mut self, ms: usize) {
+        let t = self.table_mut();
+        let wakeup_time = t.tick_count.wrapping_add(ms.min(MAX_TIMEOUT_MS));
+        let tid = t.current_thread_id.unwrap();
+        t.threads[tid].as_mut().unwrap().state = ThreadState::Blocked(BlockReason::Sleep(wakeup_time));
+    }
+
+    fn syscall_exit_thread(&mut self) {
+        let t = self.table_mut();
+        let curr_id = t.current_thread_id.expect("exit_thread: no current thread");
+        t.threads[curr_id].as_mut().unwrap().state = ThreadState::Exited;
+    }
+
+    fn current_thread_id(&self) -> usize {
+        self.table().current_thread_id.expect("no current thread")
+    }
+
+    fn idle_thread_id(&self) -> usize {
+        self.table().idle_thread_id.expect("Idle not spawned")
+    }
+
+    /// Resolve a thread handle, rejecting ones whose thread is gone.
+    fn thread_id(&self, thread: Handle) -> Result<usize, SyscallError> {
+        let t = self.table();
+        let tid = thread.index();
+        if thread.kind() != Some(ObjectKind::Thread) || tid >= MAX_THREADS {
+            return Err(SyscallError::InvalidArgument);
+        }
+        match &t.threads[tid] {
+            Some(th) if t.generations[tid] == thread.generation() && th.state != ThreadState::Exited => Ok(tid),
+            _ => Err(SyscallError::NoSuchThread),
+        }
+    }
+
+    /// Handle naming the thread in slot `tid`, as `spawn` handed it out.
+    fn thread_handle(&self, tid: usize) -> Handle {
+        Handle::new(ObjectKind::Thread, tid, self.table().generations[tid])
+    }
+
+    fn thread(&self, tid: usize) -> Option<&Thread> {
+        self.table().threads.get(tid)?.as_ref()
+    }
+
+    /// Stop `tid` from running until `resume`. A blocked thread finishes its
+    /// wait first.
+    fn suspend(&mut self, tid: usize) -> Result<(), SyscallError> {
+        let t = self.table_mut().threads[tid].as_mut().ok_or(SyscallError::NoSuchThread)?;
+        match t.state {
+            ThreadState::Ready | ThreadState::Running => t.state = ThreadState::Suspended,
+            ThreadState::Blocked(_) => t.suspend_requested = true,
+            ThreadState::Suspended => {}
+            ThreadState::Exited => return Err(SyscallError::NoSuchThread),
+        }
+        Ok(())
+    }
+
+    fn resume(&mut self, tid: usize) -> Result<(), SyscallError> {
+        let table = self.table_mut();
+        let t = table.threads[tid].as_mut().ok_or(SyscallError::NoSuchThread)?;
+        match t.state {
+            ThreadState::Suspended => {
+                t.state = ThreadState::Ready;
+                table.preempt_for(tid);
+            }
+            ThreadState::Blocked(_) => t.suspend_requested = false,
+            ThreadState::Exited => return Err(SyscallError::NoSuchThread),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Take `tid` off the CPU for good. Its objects must be released first.
+    fn kill(&mut self, tid: usize) {
+        let t = self.table_mut();
+        if t.current_thread_id == Some(tid) {
+            // still on the CPU: do_switch frees the slot once we're off it
+            t.threads[tid].as_mut().unwrap().state = ThreadState::Exited;
+        } else {
+            t.free_slot(tid);
+        }
+    }
+
+    /// Park the current thread until someone calls `wake` on it.
+    fn block_current(&mut self, reason: BlockReason) {
+        self.block_current_timeout(reason, None);
+    }
+
+    /// Like `block_current`, but `wake` the thread with `TimedOut` if nobody
+    /// else has after `timeout_ms`. `None`, or more than `MAX_TIMEOUT_MS`,
+    /// waits forever.
+    fn block_current_timeout(&mut self, reason: BlockReason, timeout_ms: Option<usize>) {
+        let t = self.table_mut();
+        let tid = t.current_thread_id.unwrap();
+        let deadline = timeout_ms.filter(|&ms| ms <= MAX_TIMEOUT_MS).map(|ms| t.tick_count.wrapping_add(ms));
+        let th = t.threads[tid].as_mut().unwrap();
+        th.state = ThreadState::Blocked(reason);
+        th.deadline = deadline;
+    }
+
+    /// A blocked thread past its deadline that `tick` left alone; see
+    /// `object::expire_timeouts`.
+    fn expired_wait(&self) -> Option<(usize, BlockReason)> {
+        let t = self.table();
+        t.threads.iter().enumerate().find_map(|(tid, th)| match th {
+            Some(th) => match th.state {
+                ThreadState::Blocked(reason) if th.deadline.is_some_and(|d| due(t.tick_count, d)) => Some((tid, reason)),
+                _ => None,
+            },
+            None => None,
+        })
+    }
+
+    /// Make a blocked thread ready, with `result` as the return value of the
+    /// syscall it blocked in.
+    fn wake(&mut self, tid: usize, result: SyscallResult) {
+        self.table_mut().wake(tid, result);
+    }
+
+    /// Make a blocked thread ready without touching its registers, for kernel
+    /// threads that blocked outside a syscall.
+    fn unblock(&mut self, tid: usize) {
+        let table = self.table_mut();
+        let t = table.threads[tid].as_mut().expect("unblock: no such thread");
+        if let ThreadState::Blocked(_) = t.state {
+            t.make_ready();
+            table.preempt_for(tid);
+        }
+    }
+}
+
+/// The fixed array of threads every policy schedules from, plus the current
+/// thread and the tick clock.
+pub struct ThreadTable {
+    pub threads: [Option<Thread>; MAX_THREADS],
+    pub current_thread_id: Option<usize>,
+    idle_thread_id: Option<usize>,
+    tick_count: usize,
+    /// Per-slot generation for thread handles, bumped when the slot is freed.
+    generations: [u16; MAX_THREADS],
+    /// (prio, ms) default time slices, see `set_prio_time_slice`.
+    prio_slices: [Option<(u32, u32)>; MAX_PRIO_TIME_SLICES],
+}
+
+impl ThreadTable {
+    pub const fn new() -> Self {
+        ThreadTable {
+            threads: [const { None }; MAX_THREADS],
+            current_thread_id: None,
+            idle_thread_id: None,
+            tick_count: 0,
+            generations: [1; MAX_THREADS],
+            prio_slices: [None; MAX_PRIO_TIME_SLICES],
+        }
+    }
+}
+
+impl Default for ThreadTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ThreadTable {
+
+    fn prio_time_slice(&self, prio: u32) -> u32 {
+        self.prio_slices.iter().flatten()
+            .find(|&&(p, _)| p == prio)
+            .map_or(DEFAULT_TIME_SLICE_MS, |&(_, ms)| ms)
+    }
+
+    /// Milliseconds since the scheduler started.
+    pub fn now(&self) -> usize {
+        self.tick_count
+    }
+
+    pub fn idle(&self) -> usize {
+        self.idle_thread_id.expect("Idle not spawned")
+    }
+
+    fn free_slot(&mut self, tid: usize) {
+        self.threads[tid] = None;
+        let g = &mut self.generations[tid];
+        *g = if *g >= MAX_GENERATION { 1 } else { *g + 1 };
+    }
+
+    /// Helper: demote curr, promote next, return raw contexts.
+    pub fn do_switch(&mut self, curr: usize, next: usize)
+                 -> Option<(*mut ThreadContext, *mut ThreadContext)> {
+        // Use split_at_mut to get two distinct &mut slots
+        let (prev_slot, next_slot) = if curr < next {
+            let (lo, hi) = self.threads.split_at_mut(next);
+            (&mut lo[curr], &mut hi[0])
+        } else {
+            let (lo, hi) = self.threads.split_at_mut(curr);
+            (&mut hi[0], &mut lo[next])
+        };
+
+        // demote or free prev_slot
+        let mut should_free_prev = false;
+        match prev_slot.as_mut().unwrap().state {
+            ThreadState::Running => prev_slot.as_mut().unwrap().state = ThreadState::Ready,
+            ThreadState::Exited  => { should_free_prev = true; },
+            _ => {}
+        }
+        // a thread that used up its turn starts the next one with a full slice
+        let prev_t = prev_slot.as_mut().unwrap();
+        if prev_t.quantum_left == 0 {
+            prev_t.quantum_left = prev_t.time_slice;
+        }
+        // promote next_slot
+        let next_t = next_slot.as_mut().unwrap();
+        next_t.state = ThreadState::Running;
+        self.current_thread_id = Some(next);
+
+        // pull out contexts
+        let prev_ctx = &mut prev_slot.as_mut().unwrap().context as *mut _;
+
+        let next_ctx = &mut next_t.context as *mut _;
+        if should_free_prev {
+            self.free_slot(curr);
+        }
+
+        Some((prev_ctx, next_ctx))
+    }
+
+    /// Spawn the non-deletable idle thread. Call this before any user threads.
+    fn spawn_idle(&mut self, fn_idle: ThreadFn) {
+        if self.idle_thread_id.is_some() {
+            panic!("Idle thread already spawned");
+        }
+        let slot = self.threads.iter().position(Option::is_none)
+            .expect("No slot for idle thread");
+        let stack_base = stack_base(slot, false);
+        let mut t = Thread::from_thread_fn(fn_idle, stack_base + STACK_SIZE, stack_base);
+        t.state = ThreadState::Ready;
+        self.threads[slot] = Some(t);
+        self.idle_thread_id = Some(slot);
+    }
+
+    /// Add a new user thread, returning its slot and handle.
+    pub fn spawn(&mut self, thread_fn: ThreadFn, attrs: ThreadAttrs) -> (usize, Handle) {
+        let slot = self.threads.iter().position(Option::is_none)
+            .expect("No available thread slot");
+        defmt::trace!("spawn: slot: {}", slot);
+        #[cfg(feature = "trustzone")]
+        let stack_base = stack_base(slot, attrs.nonsecure);
+        #[cfg(not(feature = "trustzone"))]
+        let stack_base = stack_base(slot, false);
+        let mut t = Thread::from_thread_fn_with(thread_fn, stack_base + STACK_SIZE, stack_base, attrs);
+        t.time_slice = match attrs.time_slice_ms {
+            0 => self.prio_time_slice(attrs.prio),
+            ms => ms,
+        };
+        t.quantum_left = t.time_slice;
+        t.window_start = self.tick_count;
+        #[cfg(feature = "user-heaps")]
+        let t = Thread { heap: attrs.heap, ..t };
+        #[cfg(feature = "user-heaps")]
+        if attrs.heap {
+            crate::heap::reset_heap(slot);
+        }
+        self.threads[slot] = Some(t);
+        if self.current_thread_id.is_none() {
+            self.current_thread_id = Some(slot);
+        }
+        (slot, Handle::new(ObjectKind::Thread, slot, self.generations[slot]))
+    }
+
+    /// Fixed-priority pick among the threads `eligible` lets through: highest
+    /// priority wins, round-robin among equals once the running thread's time
+    /// slice is used up, idle as fallback. Throttled threads are skipped.
+    pub fn schedule_by_prio(&mut self, eligible: impl Fn(usize) -> bool)
+                            -> Option<(*mut ThreadContext, *mut ThreadContext)> {
+        let curr = self.current_thread_id.expect("No current thread");
+        let idle = self.idle();
+
+        // 1) scan other threads first, starting after curr so equals take turns
+        let mut best: Option<(usize, u32)> = None;
+        for offset in 1..MAX_THREADS {
+            let next = (curr + offset) % MAX_THREADS;
+            if next == idle || !eligible(next) { continue; }
+
+            if let Some(th) = &self.threads[next] {
+                if th.state == ThreadState::Ready && !th.throttled && best.is_none_or(|(_, p)| th.prio > p) {
+                    best = Some((next, th.prio));
+                }
+            }
+        }
+        // a running thread gives way to a higher priority, or to an equal one
+        // once its slice is up. One that blocked and was woken again before
+        // this ran is still on the CPU, Ready: it competes like a running
+        // thread starting a fresh slice.
+        let (running_prio, expired) = match &self.threads[curr] {
+            Some(th) if curr != idle && eligible(curr) && !th.throttled => match th.state {
+                ThreadState::Running => (Some(th.prio), th.quantum_left == 0),
+                ThreadState::Ready => (Some(th.prio), false),
+                _ => (None, false),
+            },
+            _ => (None, false),
+        };
+        if let Some((next, prio)) = best {
+            if running_prio.is_none_or(|p| prio > p || (prio == p && expired)) {
+                return self.do_switch(curr, next);
+            }
+        }
+        if running_prio.is_some() {
+            // nobody to take over: carry on with a fresh slice
+            let th = self.threads[curr].as_mut().unwrap();
+            if expired || th.state == ThreadState::Ready {
+                th.quantum_left = th.time_slice;
+            }
+            th.state = ThreadState::Running;
+            return None;
+        }
+        // 2) fallback to idle if ready
+        if idle != curr {
+            if let Some(idle_t) = &mut self.threads[idle] {
+                if idle_t.state == ThreadState::Ready {
+                    return self.do_switch(curr, idle);
+                }
+            }
+        }
+        None
+    }
+
+    fn wake(&mut self, tid: usize, result: SyscallResult) {
+        let is_current = self.current_thread_id == Some(tid);
+        let t = self.threads[tid].as_mut().expect("wake: no such thread");
+
+        // a thread that hasn't been switched out yet still has its frame at
+        // PSP, the Non-secure one if it runs Non-secure
+        let frame = if is_current && t.context.exc_return & EXC_RETURN_S == 0 {
+            let psp_ns: u32;
+            unsafe {
+                core::arch::asm!("mrs {}, PSP_NS", out(reg) psp_ns, options(nomem, nostack, preserves_flags));
+            }
+            psp_ns
+        } else if is_current {
+            cortex_m::register::psp::read()
+        } else {
+            t.context.stack_addr + t.context.callee_frame_size()
+        };
+        let result = match t.wake_error.take() {
+            Some(e) if result.is_ok() => Err(e),
+            _ => result,
+        };
+        unsafe {
+            (frame as *mut usize).write_volatile(muos_syscall::error::encode(result));
+        }
+        t.make_ready();
+        self.preempt_for(tid);
+    }
+
+    /// Pend a switch if `tid`, just made ready, should take the CPU from the
+    /// current thread: it outranks it, or the current thread isn't running
+    /// any more and PendSV may not be on its way (an ISR woke a thread
+    /// that blocked, or itself, before the switch got to run).
+    fn preempt_for(&self, tid: usize) {
+        let Some(woken) = self.threads[tid].as_ref().filter(|t| t.state == ThreadState::Ready) else { return };
+        let preempts = match self.current_thread_id.and_then(|c| Some((c, self.threads[c].as_ref()?))) {
+            Some((curr, th)) if th.state == ThreadState::Running && Some(curr) != self.idle_thread_id =>
+                woken.prio > th.prio,
+            _ => true,
+        };
+        if preempts {
+            cortex_m::peripheral::SCB::set_pendsv();
+        }
+    }
+
+    /// Advance the clock by one tick: end sleeps and timed waits that are due
+    /// and charge the running thread. `true` if anything changed that might
+    /// call for a switch.
+    pub fn tick(&mut self) -> bool {
+        self.tick_count = self.tick_count.wrapping_add(SYSTICK_FREQ_MS as usize);
+        let mut resched = false;
+
+        // ready all threads that are sleeping but now past their deadline
+        for t in self.threads.iter_mut().filter_map(Option::as_mut) {
+            if let ThreadState::Blocked(BlockReason::Sleep(deadline)) = t.state {
+                if due(self.tick_count, deadline) {
+                    t.make_ready();
+                    resched = true;
+                }
+            }
+        }
+
+        // time out waits that ran past their deadline; queued waits have to
+        // leave their queue, IPC calls their server and interrupt waits their
+        // line masked; object::expire_timeouts does those
+        for tid in 0..MAX_THREADS {
+            let expired = matches!(&self.threads[tid],
+                Some(t) if matches!(t.state, ThreadState::Blocked(r)
+                    if !matches!(r, BlockReason::Object(_) | BlockReason::Futex(_) | BlockReason::Many | BlockReason::IpcReply | BlockReason::Irq(_)))
+                    && t.deadline.is_some_and(|d| due(self.tick_count, d)));
+            if expired {
+                self.wake(tid, Err(SyscallError::TimedOut));
+                resched = true;
+            }
+        }
+
+        // charge the running thread's slice and budget
+        if let Some(curr) = self.current_thread_id {
+            if Some(curr) != self.idle_thread_id {
+                if let Some(t) = self.threads[curr].as_mut().filter(|t| t.state == ThreadState::Running) {
+                    t.quantum_left = t.quantum_left.saturating_sub(SYSTICK_FREQ_MS);
+                    resched |= t.quantum_left == 0;
+                    if let Some(b) = t.cpu_budget {
+                        t.budget_used += SYSTICK_FREQ_MS;
+                        if t.budget_used >= b.budget_ms && !t.throttled {
+                            defmt::debug!("thread {} over budget, throttled", curr);
+                            t.throttled = true;
+                            resched = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        // new budget windows
+        let now = self.tick_count;
+        for t in self.threads.iter_mut().filter_map(Option::as_mut) {
+            if let Some(b) = t.cpu_budget {
+                if now.wrapping_sub(t.window_start) >= b.window_ms as usize {
+                    t.window_start = now;
+                    t.budget_used = 0;
+                    if t.throttled {
+                        t.throttled = false;
+                        resched = true;
+                    }
+                }
+            }
+        }
+        resched
+    }
+
+    /// Default time slice for threads of priority `prio` spawned from now on.
+    fn set_prio_time_slice(&mut self, prio: u32, ms: u32) -> Result<(), SyscallError> {
+        let slot = self.prio_slices.iter().position(|s| matches!(s, Some((p, _)) if *p == prio))
+            .or_else(|| self.prio_slices.iter().position(Option::is_none))
+            .ok_or(SyscallError::NoMemory)?;
+        self.prio_slices[slot] = Some((prio, ms));
+        Ok(())
+    }
+}
+
+// Global scheduler instance, stored in a Mutex/RefCell.
+static SCHEDULER: Mutex<RefCell<Option<ActiveScheduler>>> =
+    Mutex::new(RefCell::new(None));
+
+pub fn init_scheduler() {
+    interrupt::free(|cs| {
+        *SCHEDULER.borrow(cs).borrow_mut() = Some(ActiveScheduler::new());
+    });
+    spawn_idle(idle_thread as ThreadFn);
+}
+
+/// Like `with_scheduler`, for policy-specific calls.
+pub(crate) fn with_active_scheduler<F, R>(f: F) -> R
+    where
+        F: FnOnce(&mut ActiveScheduler) -> R,
+{
+    interrupt::free(|cs| {
+        let mut sched_ref = SCHEDULER.borrow(cs).borrow_mut();
+        let scheduler = sched_ref.as_mut().expect("Scheduler not initialized!");
+        f(scheduler)
+    })
+}
+
+/// Helper to access the global scheduler safely.
+pub fn with_scheduler<F, R>(f: F) -> R
+    where
+        F: FnOnce(&mut dyn Scheduler) -> R,
+{
+    with_active_scheduler(|sched| f(sched))
+}
+
+fn spawn_idle(thread_fn: ThreadFn) {
+    with_scheduler(|sched| sched.spawn_idle(thread_fn));
+}
+pub fn spawn_thread(thread_fn: ThreadFn) -> Handle {
+    spawn_thread_with(thread_fn, ThreadAttrs::default())
+}
+
+pub fn spawn_thread_with(thread_fn: ThreadFn, attrs: ThreadAttrs) -> Handle {
+    with_scheduler(|sched| sched.spawn(thread_fn, attrs))
+}
+
+/// Default time slice for threads of priority `prio` spawned from now on;
+/// up to `MAX_PRIO_TIME_SLICES` priorities can have one.
+pub fn set_prio_time_slice(prio: u32, ms: u32) -> Result<(), SyscallError> {
+    if ms == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    with_scheduler(|sched| sched.table_mut().set_prio_time_slice(prio, ms))
+}
+
+pub fn yield_now() {
+    let _ = muos_syscall::yield_now();
+}
+
+pub fn sleep_ms(ms: u32) {
+    let _ = muos_syscall::sleep_ms(ms);
+}
+
+pub fn schedule() -> Option<(*mut ThreadContext, *mut ThreadContext)> {
+    with_scheduler(|sched| sched.schedule())
+}
+
+fn idle_thread() {
+    defmt::debug!("ENTERED IDLE THREAD!");
+    loop {
+        cortex_m::asm::wfi();  // wait for next interrupt
+    }
+}
@@ -0,0 +1,43 @@
+use muos_syscall::Handle;
+use crate::thread::{ThreadAttrs, ThreadContext, ThreadFn};
+use super::{Scheduler, ThreadTable};
+
+/// Fixed-priority round-robin: the default policy.
+pub struct RRScheduler {
+    table: ThreadTable,
+}
+
+impl RRScheduler {
+    pub const fn new() -> Self {
+        RRScheduler { table: ThreadTable::new() }
+    }
+}
+
+impl Default for RRScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler for RRScheduler {
+    fn table(&self) -> &ThreadTable {
+        &self.table
+    }
+
+    fn table_mut(&mut self) -> &mut ThreadTable {
+        &mut self.table
+    }
+
+    fn spawn(&mut self, thread_fn: ThreadFn, attrs: ThreadAttrs) -> Handle {
+        self.table.spawn(thread_fn, attrs).1
+    }
+
+    /// Highest priority wins, round-robin among equals, idle as fallback.
+    fn schedule(&mut self) -> Option<(*mut ThreadContext, *mut ThreadContext)> {
+        self.table.schedule_by_prio(|_| true)
+    }
+
+    fn systick(&mut self) -> bool {
+        self.table.tick()
+    }
+}
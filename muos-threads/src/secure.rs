@@ -0,0 +1,156 @@
+//! TrustZone split: the kernel and a few secure services stay in the Secure
+//! world, application threads can be spawned Non-secure
+//! (`ThreadAttrs::nonsecure`).
+//!
+//! - The SAU marks the Non-secure code/data/stack ranges, plus the NSC region
+//!   holding the SG veneers the linker emits for every `nsc_exports!` entry
+//!   (`.gnu.sgstubs`, see `memory.x`).  Link the Secure image with
+//!   `--cmse-implib --out-implib=muos_nsc.o` and link that into the NS image.
+//! - SVC is banked, so a Non-secure thread traps into the NS image's SVCall.
+//!   With muos-syscall's `nonsecure` feature that handler just forwards its
+//!   stacked frame and r4 through the `muos_ns_svc` gate below into the
+//!   normal dispatcher; results come back in the NS frame's r0 as usual.  The gate
+//!   marks the caller Non-secure for the dispatch, so `UserPtr` and
+//!   `check_user_range` test its pointers with `TTA`/`TTAT` against the SAU
+//!   and the Non-secure MPU.
+//! - Non-secure threads get their stacks from `.uninit.ns_stacks`, which the
+//!   board's `memory.x` places in Non-secure RAM.
+//! - SysTick and PendSV stay Secure; `do_context_switch` picks the NS banked
+//!   PSP/PSPLIM/CONTROL from EXC_RETURN.S.
+//!
+//! QEMU's `mps2-an505` models all of this: its IDAU makes 0x1xxx_xxxx /
+//! 0x3xxx_xxxx Secure and the 0x0xxx_xxxx / 0x2xxx_xxxx aliases Non-secure.
+//! `muos-an505` is a single image set up that way, Secure kernel and
+//! Non-secure thread linked together.
+
+use core::mem::size_of;
+use muos_syscall::ExceptionFrame;
+use muos_syscall::user::{as_nonsecure_caller, check_nonsecure_range};
+
+const SAU_CTRL: *mut u32 = 0xE000_EDD0 as *mut u32;
+const SAU_RNR:  *mut u32 = 0xE000_EDD8 as *mut u32;
+const SAU_RBAR: *mut u32 = 0xE000_EDDC as *mut u32;
+const SAU_RLAR: *mut u32 = 0xE000_EDE0 as *mut u32;
+const NVIC_ITNS: *mut u32 = 0xE000_E380 as *mut u32;
+const SCB_SHCSR: *mut u32 = 0xE000_ED24 as *mut u32;
+
+const SAU_CTRL_ENABLE: u32 = 1 << 0;
+const SAU_RLAR_NSC:    u32 = 1 << 1;
+const SAU_RLAR_ENABLE: u32 = 1 << 0;
+const SHCSR_SECUREFAULTENA: u32 = 1 << 19;
+
+const SAU_REGIONS: usize = 8;
+
+extern "C" {
+    static __sg_start: u32;
+    static __sg_end: u32;
+}
+
+/// One SAU region. Anything not covered stays Secure.
+#[derive(Copy, Clone)]
+pub struct SauRegion {
+    pub base: u32,
+    pub limit: u32, // inclusive
+    pub nsc: bool,  // Non-secure callable instead of plain Non-secure
+}
+
+/// The region holding the linker-generated SG veneers.
+pub fn nsc_region() -> SauRegion {
+    let (start, end) = unsafe {
+        (&__sg_start as *const u32 as u32, &__sg_end as *const u32 as u32)
+    };
+    SauRegion { base: start, limit: end.saturating_sub(1), nsc: true }
+}
+
+/// Program the SAU with `regions` plus the veneer region and turn it on.
+///
+/// # Safety
+/// Secure, privileged code only, before any Non-secure code runs: whatever
+/// `regions` cover becomes reachable from the Non-secure world.
+pub unsafe fn init(regions: &[SauRegion]) {
+    assert!(regions.len() < SAU_REGIONS, "too many SAU regions");
+
+    SAU_CTRL.write_volatile(0);
+
+    let nsc = nsc_region();
+    for (i, r) in regions.iter().chain(core::iter::once(&nsc)).enumerate() {
+        program_region(i as u32, r);
+    }
+
+    SAU_CTRL.write_volatile(SAU_CTRL_ENABLE);
+
+    // report security violations as SecureFault instead of HardFault
+    SCB_SHCSR.write_volatile(SCB_SHCSR.read_volatile() | SHCSR_SECUREFAULTENA);
+
+    cortex_m::asm::dsb();
+    cortex_m::asm::isb();
+}
+
+unsafe fn program_region(region: u32, r: &SauRegion) {
+    defmt::trace!("sau: region {} {:#x}..={:#x} nsc={}", region, r.base, r.limit, r.nsc);
+    SAU_RNR.write_volatile(region);
+    SAU_RBAR.write_volatile(r.base & !0x1F);
+    let mut rlar = (r.limit & !0x1F) | SAU_RLAR_ENABLE;
+    if r.nsc { rlar |= SAU_RLAR_NSC; }
+    SAU_RLAR.write_volatile(rlar);
+}
+
+/// Route `irqn` to the Non-secure vector table (or back to the Secure one).
+///
+/// # Safety
+/// Secure, privileged code only; a Secure driver still using the line stops
+/// getting its interrupts.
+pub unsafe fn irq_target_nonsecure(irqn: u16, nonsecure: bool) {
+    let reg = NVIC_ITNS.add(irqn as usize / 32);
+    let bit = 1 << (irqn % 32);
+    if nonsecure {
+        reg.write_volatile(reg.read_volatile() | bit);
+    } else {
+        reg.write_volatile(reg.read_volatile() & !bit);
+    }
+}
+
+/// Export Secure functions to the Non-secure world.
+///
+/// ```ignore
+/// muos_threads::nsc_exports! {
+///     /// Verify the NS image before it gets to run.
+///     fn secure_boot_verify(image: *const u8, len: usize) -> u32 { ... }
+/// }
+/// ```
+///
+/// Each function gets the `cmse-nonsecure-entry` ABI, so the compiler
+/// clears registers on the way out and the linker emits an SG veneer for it
+/// into `.gnu.sgstubs`.  `NSC_EXPORTS` lists the names for the veneer table.
+#[macro_export]
+macro_rules! nsc_exports {
+    ($(
+        $(#[$meta:meta])*
+        fn $name:ident($($arg:ident: $ty:ty),* $(,)?) $(-> $ret:ty)? $body:block
+    )*) => {
+        $(
+            $(#[$meta])*
+            #[no_mangle]
+            pub extern "cmse-nonsecure-entry" fn $name($($arg: $ty),*) $(-> $ret)? $body
+        )*
+
+        /// Every function exported through the NSC veneer table.
+        pub const NSC_EXPORTS: &[&str] = &[$(stringify!($name)),*];
+    };
+}
+
+nsc_exports! {
+    /// Syscall gate for Non-secure threads.  The NS SVCall handler passes the
+    /// frame it stacked and the caller's r4; the result is written back into
+    /// the frame's r0.
+    // the Non-secure caller can't be trusted with `unsafe`: the frame is
+    // checked here instead
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
+    fn muos_ns_svc(frame: *mut ExceptionFrame, a4: usize) {
+        if check_nonsecure_range(frame as usize, size_of::<ExceptionFrame>(), true).is_err() {
+            defmt::warn!("muos_ns_svc: frame {:#x} isn't Non-secure memory", frame as usize);
+            return;
+        }
+        as_nonsecure_caller(|| unsafe { muos_syscall::syscall_dispatcher(frame, a4) })
+    }
+}
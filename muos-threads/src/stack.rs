@@ -10,9 +10,22 @@ pub(crate) struct ThreadStack {
 
 // Statically allocate the task stacks.
 #[link_section = ".uninit.stacks"]
-pub(crate) static mut THREAD_STACKS: [ThreadStack; MAX_THREADS] = [
-    ThreadStack { stack: [0; STACK_SIZE as usize] },
-    ThreadStack { stack: [0; STACK_SIZE as usize] },
-    ThreadStack { stack: [0; STACK_SIZE as usize] },
-    ThreadStack { stack: [0; STACK_SIZE as usize] },
-];
+pub(crate) static mut THREAD_STACKS: [ThreadStack; MAX_THREADS] =
+    [const { ThreadStack { stack: [0; STACK_SIZE as usize] } }; MAX_THREADS];
+
+// Non-secure threads can't use the Secure stacks above; the board's memory.x
+// has to put this section into RAM the SAU marks Non-secure.
+#[cfg(feature = "trustzone")]
+#[link_section = ".uninit.ns_stacks"]
+pub(crate) static mut NS_THREAD_STACKS: [ThreadStack; MAX_THREADS] =
+    [const { ThreadStack { stack: [0; STACK_SIZE as usize] } }; MAX_THREADS];
+
+/// Base of the stack for the thread in `slot`.
+pub(crate) fn stack_base(slot: usize, nonsecure: bool) -> u32 {
+    #[cfg(feature = "trustzone")]
+    if nonsecure {
+        return unsafe { NS_THREAD_STACKS[slot].stack.as_ptr() as u32 };
+    }
+    let _ = nonsecure;
+    unsafe { THREAD_STACKS[slot].stack.as_ptr() as u32 }
+}
@@ -0,0 +1,255 @@
+//! Stream buffers: a byte ring per object, readers woken at a trigger level.
+//!
+//! A blocked reader or writer leaves its user buffer with the stream
+//! (checked when it made the call) and whoever moves the data next, another
+//! thread or an ISR, copies straight into or out of it and wakes it with the
+//! count.  Writers are served in queue order; a write from an ISR can't wait
+//! and goes in ahead of them.
+//!
+//! `wait_many` waiters have no buffer and queue apart, as pollers: they are
+//! woken once the readers are served and the trigger level still holds, and
+//! the data stays for the next reader.
+
+use core::slice;
+use cortex_m::peripheral::SCB;
+use muos_syscall::user::check_user_range;
+use muos_syscall::{Handle, SyscallError, UserPtr, MAX_STREAM_LEN};
+use crate::object::{self, with_objects, Objects};
+use crate::scheduler::{with_scheduler, Scheduler};
+use crate::thread::BlockReason;
+use crate::waitqueue::{WaitQueue, Waiter};
+
+pub(crate) struct KStream {
+    data: [u8; MAX_STREAM_LEN],
+    capacity: usize,
+    head: usize, // oldest byte
+    len: usize,
+    trigger: usize,
+    readers: WaitQueue,
+    writers: WaitQueue,
+    pollers: WaitQueue,
+}
+
+impl KStream {
+    fn push(&mut self, src: &[u8]) -> usize {
+        let n = src.len().min(self.capacity - self.len);
+        let start = (self.head + self.len) % self.capacity;
+        let first = n.min(self.capacity - start);
+        self.data[start..start + first].copy_from_slice(&src[..first]);
+        self.data[..n - first].copy_from_slice(&src[first..n]);
+        self.len += n;
+        n
+    }
+
+    fn pop(&mut self, dst: &mut [u8]) -> usize {
+        let n = dst.len().min(self.len);
+        let first = n.min(self.capacity - self.head);
+        dst[..first].copy_from_slice(&self.data[self.head..self.head + first]);
+        dst[first..n].copy_from_slice(&self.data[..n - first]);
+        self.head = (self.head + n) % self.capacity;
+        self.len -= n;
+        n
+    }
+
+    /// Enough in for a reader with room for `cap` bytes.
+    fn ready_for(&self, cap: usize) -> bool {
+        self.len >= self.trigger.min(cap)
+    }
+}
+
+/// User buffer of a thread blocked on a stream.
+#[derive(Copy, Clone)]
+pub(crate) struct StreamIo {
+    buf: usize,
+    len: usize,
+    done: usize, // bytes a writer got in so far
+    write: bool,
+}
+
+impl StreamIo {
+    pub(crate) const EMPTY: StreamIo = StreamIo { buf: 0, len: 0, done: 0, write: false };
+
+    // checked by the syscall that queued the thread, which is still blocked in it
+    unsafe fn pending(&self) -> &'static [u8] {
+        slice::from_raw_parts((self.buf + self.done) as *const u8, self.len - self.done)
+    }
+
+    unsafe fn space(&self) -> &'static mut [u8] {
+        slice::from_raw_parts_mut(self.buf as *mut u8, self.len)
+    }
+}
+
+pub(crate) fn create(owner: usize, capacity: usize, trigger: usize) -> Result<Handle, SyscallError> {
+    if capacity == 0 || capacity > MAX_STREAM_LEN || trigger > capacity {
+        return Err(SyscallError::InvalidArgument);
+    }
+    with_objects(|o| o.streams.alloc(owner, KStream {
+        data: [0; MAX_STREAM_LEN],
+        capacity,
+        head: 0,
+        len: 0,
+        trigger: trigger.max(1),
+        readers: WaitQueue::new(),
+        writers: WaitQueue::new(),
+        pollers: WaitQueue::new(),
+    }))
+}
+
+/// Move data between the ring and the queued threads for as long as anyone
+/// can make progress. `true` if anyone woke.
+fn pump(o: &mut Objects, sched: &mut dyn Scheduler, handle: Handle) -> bool {
+    let mut woke = false;
+    loop {
+        let Ok(s) = o.streams.get_mut(handle) else { return woke };
+        let mut moved = false;
+        if let Some((tid, _)) = s.writers.peek() {
+            let io = &mut o.stream_io[tid];
+            let n = s.push(unsafe { io.pending() });
+            io.done += n;
+            moved = n > 0;
+            if io.done == io.len {
+                s.writers.pop(sched);
+                sched.wake(tid, Ok(io.done));
+                woke = true;
+            }
+        }
+        if let Some((tid, _)) = s.readers.peek() {
+            let io = &o.stream_io[tid];
+            if s.ready_for(io.len) {
+                let n = s.pop(unsafe { io.space() });
+                s.readers.pop(sched);
+                sched.wake(tid, Ok(n));
+                woke = true;
+                moved = true;
+            }
+        }
+        if !moved {
+            // what the readers left may still be enough for `wait_many`
+            while let Some(w) = o.streams.get_mut(handle).ok()
+                .filter(|s| s.ready_for(usize::MAX))
+                .and_then(|s| s.pollers.pop(sched))
+            {
+                object::complete(o, sched, w, Ok(0));
+                woke = true;
+            }
+            return woke;
+        }
+    }
+}
+
+/// `Some(written)` if the caller is done, `None` if it was blocked for the rest.
+pub(crate) fn write(handle: Handle, data: UserPtr<u8>, len: u32, timeout_ms: Option<usize>) -> Result<Option<usize>, SyscallError> {
+    let len = len as usize;
+    check_user_range(data.addr(), len, false)?;
+    let data = unsafe { slice::from_raw_parts(data.addr() as *const u8, len) };
+    with_objects(|o| with_scheduler(|sched| {
+        let tid = sched.current_thread_id();
+        let mut done = 0;
+        loop {
+            let s = o.streams.get_mut(handle)?;
+            if s.writers.is_empty() {
+                done += s.push(&data[done..]);
+            }
+            // readers taking their share may make room for more
+            let woke = pump(o, sched, handle);
+            if done == len || !woke {
+                break;
+            }
+        }
+        if done == len {
+            return Ok(Some(done));
+        }
+        if timeout_ms == Some(0) {
+            return if done > 0 { Ok(Some(done)) } else { Err(SyscallError::WouldBlock) };
+        }
+        o.stream_io[tid] = StreamIo { buf: data.as_ptr() as usize, len, done, write: true };
+        o.streams.get_mut(handle)?.writers.block_current(sched, BlockReason::Object(handle), timeout_ms);
+        Ok(None)
+    }))
+}
+
+/// `Some(read)` if the caller is done, `None` if it was blocked until the
+/// trigger level.
+pub(crate) fn read(handle: Handle, buf: UserPtr<u8>, cap: u32, timeout_ms: Option<usize>) -> Result<Option<usize>, SyscallError> {
+    let cap = cap as usize;
+    check_user_range(buf.addr(), cap, true)?;
+    let io = StreamIo { buf: buf.addr(), len: cap, done: 0, write: false };
+    with_objects(|o| with_scheduler(|sched| {
+        let tid = sched.current_thread_id();
+        let s = o.streams.get_mut(handle)?;
+        // queued readers go first, a zero timeout only means taking less
+        if s.readers.is_empty() && (s.ready_for(cap) || timeout_ms == Some(0)) {
+            let n = s.pop(unsafe { io.space() });
+            pump(o, sched, handle);
+            return if n > 0 || cap == 0 { Ok(Some(n)) } else { Err(SyscallError::WouldBlock) };
+        }
+        if timeout_ms == Some(0) {
+            return Err(SyscallError::WouldBlock);
+        }
+        o.stream_io[tid] = io;
+        s.readers.block_current(sched, BlockReason::Object(handle), timeout_ms);
+        Ok(None)
+    }))
+}
+
+/// Queue `data` from an interrupt handler (or any privileged code) without
+/// blocking. Returns how much fit; the rest is dropped.
+pub fn isr_write(handle: Handle, data: &[u8]) -> Result<usize, SyscallError> {
+    let (n, woke) = with_objects(|o| with_scheduler(|sched| {
+        let n = o.streams.get_mut(handle)?.push(data);
+        Ok::<_, SyscallError>((n, pump(o, sched, handle)))
+    }))?;
+    if woke {
+        SCB::set_pendsv();
+    }
+    Ok(n)
+}
+
+/// `tid`'s wait on `handle` timed out and it's off the queue: hand it what
+/// it got so far, `TimedOut` if that's nothing.
+pub(crate) fn timed_out(o: &mut Objects, sched: &mut dyn Scheduler, handle: Handle, tid: usize) {
+    let io = o.stream_io[tid];
+    let n = match o.streams.get_mut(handle) {
+        Ok(_) if io.write => io.done,
+        Ok(s) => s.pop(unsafe { io.space() }),
+        Err(_) => 0,
+    };
+    sched.wake(tid, if n > 0 { Ok(n) } else { Err(SyscallError::TimedOut) });
+    pump(o, sched, handle);
+}
+
+/// Up to the trigger level, for `wait_many`.
+pub(crate) fn readable(o: &mut Objects, handle: Handle) -> Result<bool, SyscallError> {
+    Ok(o.streams.get_mut(handle)?.ready_for(usize::MAX))
+}
+
+pub(crate) fn poll_queue(o: &mut Objects, handle: Handle) -> Result<&mut WaitQueue, SyscallError> {
+    Ok(&mut o.streams.get_mut(handle)?.pollers)
+}
+
+pub(crate) fn queue(o: &mut Objects, handle: Handle, writers: bool) -> Result<&mut WaitQueue, SyscallError> {
+    let s = o.streams.get_mut(handle)?;
+    Ok(if writers { &mut s.writers } else { &mut s.readers })
+}
+
+/// Take `waiter` off the queues of `handle`.
+pub(crate) fn dequeue(o: &mut Objects, sched: &mut dyn Scheduler, handle: Handle, waiter: Waiter) -> bool {
+    let Ok(s) = o.streams.get_mut(handle) else { return false };
+    s.readers.remove(sched, waiter) || s.writers.remove(sched, waiter) || s.pollers.remove(sched, waiter)
+}
+
+pub(crate) fn destroy(handle: Handle, caller: usize) -> Result<(), SyscallError> {
+    with_objects(|o| with_scheduler(|sched| {
+        if o.streams.owner(handle)? != caller {
+            return Err(SyscallError::NotPermitted);
+        }
+        let mut s = o.streams.free(handle)?;
+        while let Some((tid, _)) = s.readers.pop(sched).or_else(|| s.writers.pop(sched)) {
+            sched.wake(tid, Err(SyscallError::ObjectRemoved));
+        }
+        while let Some(w) = s.pollers.pop(sched) {
+            object::complete(o, sched, w, Err(SyscallError::ObjectRemoved));
+        }
+        Ok(())
+    }))
+}
@@ -0,0 +1,351 @@
+use muos_syscall::{Handle, IpcCall, NotifyAction, SyscallError, Syscalls, UserPtr, WaitSpec, WAIT_FOREVER};
+use crate::asm::do_setup;
+use crate::memory::{mpu_program_heap, mpu_program_thread};
+use crate::bootinfo::{self, ResetReason};
+use crate::{barrier, cond, futex, ipc, irq, latch, mutex, notify, object, rwlock, scheduler, stream, waitmany, watchdog, work};
+
+/// Kernel side of the syscall table declared in `muos_syscall::calls`.
+pub(crate) struct Kernel;
+
+/// Give back everything a dying thread holds, before its slot goes.
+fn reap(tid: usize) {
+    object::release_thread(tid);
+    irq::release_thread(tid);
+    watchdog::release_thread(tid);
+}
+
+fn caller_privileged() -> bool {
+    scheduler::with_scheduler(|sched| sched.thread(sched.current_thread_id()).is_some_and(|t| t.privileged))
+}
+
+/// Resolve `thread` to a slot the caller may control: itself, or any user
+/// thread if the caller is privileged.
+fn target(thread: Handle) -> Result<usize, SyscallError> {
+    scheduler::with_scheduler(|sched| {
+        let tid = sched.thread_id(thread)?;
+        let caller = sched.current_thread_id();
+        if tid == sched.idle_thread_id() || work::is_worker(tid) {
+            return Err(SyscallError::NotPermitted);
+        }
+        if tid != caller && !sched.thread(caller).is_some_and(|t| t.privileged) {
+            return Err(SyscallError::NotPermitted);
+        }
+        Ok(tid)
+    })
+}
+
+impl Syscalls for Kernel {
+    unsafe fn scheduler_boot() -> Result<(), SyscallError> {
+        defmt::trace!("boot handler");
+        let (psp, ctrl, eret, psplim, stack_base, stack_size, heap) =
+            scheduler::with_scheduler(|s| {
+                let (psp, ctrl, eret, psplim) = s.get_initial_thread_registers();
+                let (stack_base, stack_size) = s.get_current_thread_stack();
+
+                (psp, ctrl, eret, psplim, stack_base, stack_size, s.get_current_thread_heap())
+            });
+
+        mpu_program_thread(stack_base, stack_size);
+        mpu_program_heap(heap);
+        do_setup(psp, ctrl, eret, psplim)
+    }
+
+    unsafe fn yield_now() -> Result<(), SyscallError> {
+        defmt::trace!("yield handler");
+        scheduler::with_scheduler(|sched| sched.yield_current());
+        cortex_m::peripheral::SCB::set_pendsv();
+        Ok(())
+    }
+
+    unsafe fn sleep_ms(ms: u32) -> Result<(), SyscallError> {
+        defmt::trace!("sleep_ms handler: {}", ms);
+        scheduler::with_scheduler(|sched| sched.syscall_sleep_ms(ms as usize));
+        cortex_m::peripheral::SCB::set_pendsv();
+        Ok(())
+    }
+
+    unsafe fn exit_thread() -> Result<(), SyscallError> {
+        defmt::trace!("exit handler");
+        let tid = scheduler::with_scheduler(|sched| sched.current_thread_id());
+        reap(tid);
+        scheduler::with_scheduler(|sched| sched.syscall_exit_thread());
+        cortex_m::peripheral::SCB::set_pendsv();
+        Ok(())
+    }
+
+    unsafe fn mutex_create() -> Result<Handle, SyscallError> {
+        let tid = scheduler::with_scheduler(|sched| sched.current_thread_id());
+        mutex::create(tid)
+    }
+
+    unsafe fn mutex_lock(m: Handle, timeout_ms: u32) -> Result<(), SyscallError> {
+        defmt::trace!("mutex_lock handler: {:#x}", m.raw());
+        let timeout = (timeout_ms != WAIT_FOREVER).then_some(timeout_ms as usize);
+        if mutex::lock(m, timeout)? {
+            cortex_m::peripheral::SCB::set_pendsv();
+            return Err(SyscallError::Blocked);
+        }
+        Ok(())
+    }
+
+    unsafe fn mutex_unlock(m: Handle) -> Result<(), SyscallError> {
+        defmt::trace!("mutex_unlock handler: {:#x}", m.raw());
+        mutex::unlock(m)
+    }
+
+    unsafe fn object_destroy(h: Handle) -> Result<(), SyscallError> {
+        let tid = scheduler::with_scheduler(|sched| sched.current_thread_id());
+        object::destroy(h, tid)
+    }
+
+    unsafe fn irq_wait(irqn: u16, timeout_ms: u32) -> Result<(), SyscallError> {
+        defmt::trace!("irq_wait handler: {} {}", irqn, timeout_ms);
+        let timeout = (timeout_ms != WAIT_FOREVER).then_some(timeout_ms as usize);
+        irq::wait(irqn, timeout)?;
+        cortex_m::peripheral::SCB::set_pendsv();
+        Err(SyscallError::Blocked)
+    }
+
+    unsafe fn irq_attach(irqn: u16) -> Result<(), SyscallError> {
+        let tid = scheduler::with_scheduler(|sched| sched.current_thread_id());
+        irq::attach(irqn, tid, caller_privileged())
+    }
+
+    unsafe fn irq_grant(irqn: u16, thread: Handle) -> Result<(), SyscallError> {
+        if !caller_privileged() {
+            return Err(SyscallError::NotPermitted);
+        }
+        let tid = scheduler::with_scheduler(|sched| sched.thread_id(thread))?;
+        irq::grant(irqn, tid)
+    }
+
+    unsafe fn thread_suspend(thread: Handle) -> Result<(), SyscallError> {
+        let tid = target(thread)?;
+        scheduler::with_scheduler(|sched| sched.suspend(tid))?;
+        cortex_m::peripheral::SCB::set_pendsv();
+        Ok(())
+    }
+
+    unsafe fn thread_resume(thread: Handle) -> Result<(), SyscallError> {
+        let tid = target(thread)?;
+        scheduler::with_scheduler(|sched| sched.resume(tid))?;
+        cortex_m::peripheral::SCB::set_pendsv();
+        Ok(())
+    }
+
+    unsafe fn thread_kill(thread: Handle) -> Result<(), SyscallError> {
+        let tid = target(thread)?;
+        defmt::debug!("thread_kill: {}", tid);
+        reap(tid);
+        scheduler::with_scheduler(|sched| sched.kill(tid));
+        cortex_m::peripheral::SCB::set_pendsv();
+        Ok(())
+    }
+
+    unsafe fn wdt_register(timeout_ms: u32) -> Result<(), SyscallError> {
+        let tid = scheduler::with_scheduler(|sched| sched.current_thread_id());
+        watchdog::register(tid, timeout_ms)
+    }
+
+    unsafe fn wdt_feed() -> Result<(), SyscallError> {
+        let tid = scheduler::with_scheduler(|sched| sched.current_thread_id());
+        watchdog::feed(tid)
+    }
+
+    unsafe fn system_reset(code: u32) -> Result<(), SyscallError> {
+        if !caller_privileged() {
+            return Err(SyscallError::NotPermitted);
+        }
+        defmt::warn!("system_reset: {:#x}", code);
+        bootinfo::record(ResetReason::Software, code);
+        cortex_m::peripheral::SCB::sys_reset()
+    }
+
+    unsafe fn period_wait() -> Result<(), SyscallError> {
+        scheduler::with_scheduler(|sched| sched.wait_next_period())?;
+        cortex_m::peripheral::SCB::set_pendsv();
+        Ok(())
+    }
+
+    unsafe fn cond_create() -> Result<Handle, SyscallError> {
+        let tid = scheduler::with_scheduler(|sched| sched.current_thread_id());
+        cond::create(tid)
+    }
+
+    unsafe fn cond_wait(c: Handle, m: Handle, timeout_ms: u32) -> Result<(), SyscallError> {
+        defmt::trace!("cond_wait handler: {:#x} {:#x}", c.raw(), m.raw());
+        let timeout = (timeout_ms != WAIT_FOREVER).then_some(timeout_ms as usize);
+        cond::wait(c, m, timeout)?;
+        cortex_m::peripheral::SCB::set_pendsv();
+        Err(SyscallError::Blocked)
+    }
+
+    unsafe fn cond_signal(c: Handle) -> Result<(), SyscallError> {
+        if cond::signal(c, false)? {
+            cortex_m::peripheral::SCB::set_pendsv();
+        }
+        Ok(())
+    }
+
+    unsafe fn cond_broadcast(c: Handle) -> Result<(), SyscallError> {
+        if cond::signal(c, true)? {
+            cortex_m::peripheral::SCB::set_pendsv();
+        }
+        Ok(())
+    }
+
+    unsafe fn rwlock_create(prefer_writer: bool) -> Result<Handle, SyscallError> {
+        let tid = scheduler::with_scheduler(|sched| sched.current_thread_id());
+        rwlock::create(tid, prefer_writer)
+    }
+
+    unsafe fn rwlock_read(l: Handle, timeout_ms: u32) -> Result<(), SyscallError> {
+        let timeout = (timeout_ms != WAIT_FOREVER).then_some(timeout_ms as usize);
+        if rwlock::lock(l, false, timeout)? {
+            cortex_m::peripheral::SCB::set_pendsv();
+            return Err(SyscallError::Blocked);
+        }
+        Ok(())
+    }
+
+    unsafe fn rwlock_write(l: Handle, timeout_ms: u32) -> Result<(), SyscallError> {
+        let timeout = (timeout_ms != WAIT_FOREVER).then_some(timeout_ms as usize);
+        if rwlock::lock(l, true, timeout)? {
+            cortex_m::peripheral::SCB::set_pendsv();
+            return Err(SyscallError::Blocked);
+        }
+        Ok(())
+    }
+
+    unsafe fn rwlock_unlock(l: Handle) -> Result<(), SyscallError> {
+        rwlock::unlock(l)
+    }
+
+    unsafe fn futex_wait(addr: UserPtr<u32>, expected: u32, timeout_ms: u32) -> Result<(), SyscallError> {
+        let timeout = (timeout_ms != WAIT_FOREVER).then_some(timeout_ms as usize);
+        futex::wait(addr, expected, timeout)?;
+        cortex_m::peripheral::SCB::set_pendsv();
+        Err(SyscallError::Blocked)
+    }
+
+    unsafe fn futex_wake(addr: UserPtr<u32>, count: u32) -> Result<u32, SyscallError> {
+        let woken = futex::wake(addr, count)?;
+        if woken > 0 {
+            cortex_m::peripheral::SCB::set_pendsv();
+        }
+        Ok(woken)
+    }
+
+    unsafe fn wait_many_raw(specs: UserPtr<WaitSpec>, count: u32, timeout_ms: u32) -> Result<u32, SyscallError> {
+        let timeout = (timeout_ms != WAIT_FOREVER).then_some(timeout_ms as usize);
+        match waitmany::wait_many(specs, count, timeout)? {
+            Some(index) => Ok(index as u32),
+            None => {
+                // the index comes from whichever object wakes us
+                cortex_m::peripheral::SCB::set_pendsv();
+                Err(SyscallError::Blocked)
+            }
+        }
+    }
+
+    unsafe fn endpoint_create() -> Result<Handle, SyscallError> {
+        let tid = scheduler::with_scheduler(|sched| sched.current_thread_id());
+        ipc::create(tid)
+    }
+
+    unsafe fn ipc_call_raw(endpoint: Handle, call: UserPtr<IpcCall>, timeout_ms: u32) -> Result<u32, SyscallError> {
+        let timeout = (timeout_ms != WAIT_FOREVER).then_some(timeout_ms as usize);
+        ipc::call(endpoint, call, timeout)?;
+        // the reply length comes with the wake
+        cortex_m::peripheral::SCB::set_pendsv();
+        Err(SyscallError::Blocked)
+    }
+
+    unsafe fn ipc_recv_raw(endpoint: Handle, buf: UserPtr<u8>, cap: u32, len: UserPtr<u32>, timeout_ms: u32) -> Result<Handle, SyscallError> {
+        let timeout = (timeout_ms != WAIT_FOREVER).then_some(timeout_ms as usize);
+        match ipc::recv(endpoint, buf, cap, len, timeout)? {
+            Some(client) => Ok(client),
+            None => {
+                // the client handle comes with the wake
+                cortex_m::peripheral::SCB::set_pendsv();
+                Err(SyscallError::Blocked)
+            }
+        }
+    }
+
+    unsafe fn ipc_reply_raw(client: Handle, msg: UserPtr<u8>, len: u32) -> Result<(), SyscallError> {
+        ipc::reply(client, msg, len)?;
+        cortex_m::peripheral::SCB::set_pendsv();
+        Ok(())
+    }
+
+    unsafe fn stream_create(capacity: u32, trigger: u32) -> Result<Handle, SyscallError> {
+        let tid = scheduler::with_scheduler(|sched| sched.current_thread_id());
+        stream::create(tid, capacity as usize, trigger as usize)
+    }
+
+    unsafe fn stream_write_raw(s: Handle, data: UserPtr<u8>, len: u32, timeout_ms: u32) -> Result<u32, SyscallError> {
+        let timeout = (timeout_ms != WAIT_FOREVER).then_some(timeout_ms as usize);
+        let written = stream::write(s, data, len, timeout)?;
+        // readers may have woken either way
+        cortex_m::peripheral::SCB::set_pendsv();
+        // if we blocked, the count comes with the wake
+        written.map(|n| n as u32).ok_or(SyscallError::Blocked)
+    }
+
+    unsafe fn stream_read_raw(s: Handle, buf: UserPtr<u8>, cap: u32, timeout_ms: u32) -> Result<u32, SyscallError> {
+        let timeout = (timeout_ms != WAIT_FOREVER).then_some(timeout_ms as usize);
+        let read = stream::read(s, buf, cap, timeout)?;
+        // blocked, or made room for a writer
+        cortex_m::peripheral::SCB::set_pendsv();
+        read.map(|n| n as u32).ok_or(SyscallError::Blocked)
+    }
+
+    unsafe fn notify_raw(thread: Handle, value: u32, action: u32) -> Result<(), SyscallError> {
+        let action = NotifyAction::from_raw(action).ok_or(SyscallError::InvalidArgument)?;
+        notify::notify(thread, value, action)
+    }
+
+    unsafe fn notify_wait_raw(clear_on_entry: u32, clear_on_exit: u32, value: UserPtr<u32>, timeout_ms: u32) -> Result<(), SyscallError> {
+        let timeout = (timeout_ms != WAIT_FOREVER).then_some(timeout_ms as usize);
+        if notify::wait(clear_on_entry, clear_on_exit, value, timeout)? {
+            cortex_m::peripheral::SCB::set_pendsv();
+            return Err(SyscallError::Blocked);
+        }
+        Ok(())
+    }
+
+    unsafe fn barrier_create(parties: u32) -> Result<Handle, SyscallError> {
+        let tid = scheduler::with_scheduler(|sched| sched.current_thread_id());
+        barrier::create(tid, parties)
+    }
+
+    unsafe fn barrier_wait(b: Handle, timeout_ms: u32) -> Result<u32, SyscallError> {
+        let timeout = (timeout_ms != WAIT_FOREVER).then_some(timeout_ms as usize);
+        let serial = barrier::wait(b, timeout)?;
+        // either we blocked or we released the others
+        cortex_m::peripheral::SCB::set_pendsv();
+        if serial { Ok(1) } else { Err(SyscallError::Blocked) }
+    }
+
+    unsafe fn latch_create(count: u32) -> Result<Handle, SyscallError> {
+        let tid = scheduler::with_scheduler(|sched| sched.current_thread_id());
+        latch::create(tid, count)
+    }
+
+    unsafe fn latch_count_down(l: Handle) -> Result<(), SyscallError> {
+        if latch::count_down(l)? {
+            cortex_m::peripheral::SCB::set_pendsv();
+        }
+        Ok(())
+    }
+
+    unsafe fn latch_wait(l: Handle, timeout_ms: u32) -> Result<(), SyscallError> {
+        let timeout = (timeout_ms != WAIT_FOREVER).then_some(timeout_ms as usize);
+        if latch::wait(l, timeout)? {
+            cortex_m::peripheral::SCB::set_pendsv();
+            return Err(SyscallError::Blocked);
+        }
+        Ok(())
+    }
+}
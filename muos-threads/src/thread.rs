@@ -1,10 +1,28 @@
 #![no_std]
 
+use muos_syscall::{Handle, SyscallError};
+
 pub type ThreadFn = fn() -> ();
 
 #[derive(Copy, Clone, PartialEq)]
 pub enum BlockReason {
     Sleep(usize),
+    /// Queued on a kernel object's `WaitQueue`.
+    Object(Handle),
+    /// In `futex_wait` on the given user address.
+    Futex(usize),
+    /// In `wait_many`, queued on every object it named.
+    Many,
+    /// In `ipc_call`, received by a server and waiting for its reply.
+    IpcReply,
+    /// In `notify_wait`, with no notification pending.
+    Notify,
+    /// The kernel worker, waiting for deferred work.
+    Work,
+    /// Waiting in `irq_wait` for the given NVIC interrupt.
+    Irq(u16),
+    /// Periodic thread done with its job, waiting for the next release.
+    NextPeriod,
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -12,12 +30,88 @@ pub enum ThreadState {
     Ready,
     Running,
     Blocked(BlockReason),
+    /// Stopped by `thread_suspend` until `thread_resume`.
+    Suspended,
     Exited,
 }
 
+/// EXC_RETURN for thread mode on PSP with a basic (integer-only) frame.
+pub const EXC_RETURN_THREAD_PSP: u32 = 0xFFFF_FFFD;
+/// EXC_RETURN for thread mode on PSP with an extended (FP) frame.
+pub const EXC_RETURN_THREAD_PSP_FP: u32 = 0xFFFF_FFED;
+/// EXC_RETURN.FType: cleared when the stacked frame includes FP state.
+pub const EXC_RETURN_FTYPE: u32 = 1 << 4;
+/// EXC_RETURN.S: cleared when the frame sits on the Non-secure stack.
+pub const EXC_RETURN_S: u32 = 1 << 6;
+
+pub const CONTROL_NPRIV: u32 = 1 << 0; // thread mode is unprivileged
+pub const CONTROL_SPSEL: u32 = 1 << 1; // thread mode runs on PSP
+pub const CONTROL_FPCA: u32 = 1 << 2;  // FP context active
+
+pub(crate) const CALLEE_REGS_SIZE: u32 = 8 * 4;     // r4-r11
+pub(crate) const CALLEE_FP_REGS_SIZE: u32 = 16 * 4; // s16-s31
+const EXC_FRAME_SIZE: u32 = 8 * 4;                  // r0-r3, r12, lr, pc, xpsr
+const EXC_FP_FRAME_SIZE: u32 = 18 * 4;              // s0-s15, fpscr, reserved
+
+/// Saved state of a switched-out thread. Layout is shared with `do_context_switch`
+/// and `do_setup`, so only append fields.
 #[derive(Copy, Clone)]
+#[repr(C)]
 pub struct ThreadContext {
     pub stack_addr: u32,
+    pub exc_return: u32,
+    pub control: u32, // nPRIV | SPSEL | FPCA as seen by the thread
+    pub psplim: u32,  // lowest valid PSP, i.e. the stack base
+}
+
+impl ThreadContext {
+    /// Whether the thread's saved frame carries FP state (EXC_RETURN bit 4 clear).
+    pub fn uses_fp(&self) -> bool {
+        self.exc_return & EXC_RETURN_FTYPE == 0
+    }
+
+    /// Size of the software-saved register block sitting below the exception frame.
+    pub fn callee_frame_size(&self) -> u32 {
+        if self.uses_fp() {
+            CALLEE_REGS_SIZE + CALLEE_FP_REGS_SIZE
+        } else {
+            CALLEE_REGS_SIZE
+        }
+    }
+}
+
+/// Time slice for threads that don't ask for one and whose priority has
+/// none configured.
+pub const DEFAULT_TIME_SLICE_MS: u32 = crate::SYSTICK_FREQ_MS;
+
+/// CPU share limit: at most `budget_ms` of run time in every `window_ms`.
+#[derive(Copy, Clone, defmt::Format)]
+pub struct CpuBudget {
+    pub budget_ms: u32,
+    pub window_ms: u32,
+}
+
+/// Spawn-time options for a thread.
+#[derive(Copy, Clone, Default)]
+pub struct ThreadAttrs {
+    pub prio: u32,
+    pub privileged: bool,
+    pub fp: bool,
+    /// Run time before an equal-priority thread gets a turn; 0 uses the
+    /// priority's default, see `scheduler::set_prio_time_slice`.
+    pub time_slice_ms: u32,
+    /// Throttle the thread once it exceeds this share of the CPU.
+    pub cpu_budget: Option<CpuBudget>,
+    /// Run the thread in the Non-secure world. The entry function must then
+    /// live in Non-secure code and never return.
+    #[cfg(feature = "trustzone")]
+    pub nonsecure: bool,
+    /// Give the thread a private heap for `muos-alloc`.
+    #[cfg(feature = "user-heaps")]
+    pub heap: bool,
+    /// Make this a periodic thread under the EDF scheduler.
+    #[cfg(feature = "edf")]
+    pub edf: Option<crate::scheduler::edf::EdfParams>,
 }
 
 #[derive(Copy, Clone)]
@@ -28,31 +122,74 @@ pub struct Thread {
     pub fn_addr: u32,
     pub privileged: bool,
     pub fp: bool, // whether thread starts with floating point context enabled
+    pub heap: bool, // whether the slot's heap gets mapped for this thread
     pub state: ThreadState,
+    /// Tick at which a blocked thread gives up with `TimedOut`.
+    pub deadline: Option<usize>,
+    /// Suspended while blocked: finish the wait, then park instead of running.
+    pub suspend_requested: bool,
+    pub time_slice: u32,   // ms per turn among equal priorities
+    pub quantum_left: u32, // ms left of the current turn
+    pub cpu_budget: Option<CpuBudget>,
+    pub budget_used: u32,  // ms run in the current budget window
+    pub window_start: usize,
+    /// Over budget: not scheduled until the window rolls over.
+    pub throttled: bool,
+    /// Reported by the next `wake` instead of success: a `cond_wait` that
+    /// timed out still has to get its mutex back first.
+    pub wake_error: Option<SyscallError>,
+    /// Next entry of each wait queue this one is on, per wait slot.
+    pub wait_next: [Option<(usize, usize)>; muos_syscall::MAX_WAIT_SPECS],
+    /// Priority before IPC donation; `prio` is what the scheduler uses.
+    pub base_prio: u32,
+    /// Notification word, see `notify`.
+    pub notify_value: u32,
+    /// Notified since the last `notify_wait` took the word.
+    pub notify_pending: bool,
+    /// While in `notify_wait` (or `wait_many` on it): where the word goes,
+    /// and what to clear after.
+    pub notify_out: usize,
+    pub notify_clear_on_exit: u32,
 }
 
 impl Thread {
     pub fn new(
         stack_addr: u32,
+        stack_limit: u32,
         prio: u32,
         fn_addr: u32,
         privileged: bool,
         fp: bool,
+        nonsecure: bool,
     ) -> Self {
-        const CALLEE_REGS_SIZE: u32 = 8 * 4;
-        const EXC_FRAME_SIZE: u32 = 8 * 4;
         let stack_top = stack_addr & !0x7;  // enforce 8-byte alignment at top
 
+        // fp threads start out with an extended frame, so the first exception
+        // return already has FPCA set and S16-S31 get a slot in the callee area
+        let (mut exc_return, frame_size, callee_size) = if fp {
+            (EXC_RETURN_THREAD_PSP_FP, EXC_FRAME_SIZE + EXC_FP_FRAME_SIZE, CALLEE_REGS_SIZE + CALLEE_FP_REGS_SIZE)
+        } else {
+            (EXC_RETURN_THREAD_PSP, EXC_FRAME_SIZE, CALLEE_REGS_SIZE)
+        };
+        // Non-secure threads can't call back into the Secure trampoline, so
+        // they enter their function directly on the NS stack
+        let (arg, entry) = if nonsecure {
+            exc_return &= !EXC_RETURN_S;
+            (0, fn_addr & !1)
+        } else {
+            (fn_addr, (thread_trampoline as u32) | 1)
+        };
+
         // Allocate space for both frames explicitly:
-        let frame_start = (stack_top - EXC_FRAME_SIZE) & !0x7;
-        let regs_start  = (frame_start - CALLEE_REGS_SIZE) & !0x7;
+        let frame_start = (stack_top - frame_size) & !0x7;
+        let regs_start  = (frame_start - callee_size) & !0x7;
 
         assert!(frame_start % 8 == 0 && regs_start % 8 == 0);
 
         unsafe {
-            // clear callee-saved regs
+            // clear callee-saved regs (and s16-s31 for fp threads)
             let mut ptr = regs_start as *mut u32;
-            for _ in 0..8 {
+            for _ in 0..(callee_size / 4) {
                 ptr.write(0);
                 ptr = ptr.add(1);
             }
@@ -60,48 +197,92 @@ impl Thread {
             // write initial exception frame
             let frame_ptr = frame_start as *mut u32;
             let frame = [
-                fn_addr,            // R0: argument (thread entry fn)
+                arg,                // R0: argument (thread entry fn)
                 0,                  // R1
                 0,                  // R2
                 0,                  // R3
                 0,                  // R12
                 0xFFFFFFFD,         // LR (return to thread mode using PSP)
-                (thread_trampoline as u32) | 1,  // PC (thread entry point)
+                entry,              // PC (thread entry point)
                 0x01000000,         // xPSR (Thumb mode)
             ];
 
             for (i, &w) in frame.iter().enumerate() {
                 frame_ptr.add(i).write(w);
             }
+
+            // clear s0-s15, FPSCR and the reserved word of the extended frame
+            for i in frame.len()..(frame_size / 4) as usize {
+                frame_ptr.add(i).write(0);
+            }
         }
 
+        let mut control = CONTROL_SPSEL;
+        if !privileged { control |= CONTROL_NPRIV; }
+        if fp { control |= CONTROL_FPCA; }
+
         Thread {
-            context: ThreadContext { stack_addr: regs_start },
+            context: ThreadContext {
+                stack_addr: regs_start,
+                exc_return,
+                control,
+                psplim: (stack_limit + 7) & !0x7, // PSPLIM[2:0] are RES0
+            },
             prio,
             fn_addr,
             privileged,
             fp,
+            heap: false,
             state: ThreadState::Ready,
+            deadline: None,
+            suspend_requested: false,
+            time_slice: DEFAULT_TIME_SLICE_MS,
+            quantum_left: DEFAULT_TIME_SLICE_MS,
+            cpu_budget: None,
+            budget_used: 0,
+            window_start: 0,
+            throttled: false,
+            wake_error: None,
+            wait_next: [None; muos_syscall::MAX_WAIT_SPECS],
+            base_prio: prio,
+            notify_value: 0,
+            notify_pending: false,
+            notify_out: 0,
+            notify_clear_on_exit: 0,
         }
     }
 
-    pub fn from_thread_fn(thread_fn: ThreadFn, stack_addr: u32) -> Self {
-        defmt::trace!("thread: from_thread_fn: stack addr: {:#x}", stack_addr);
-        Self::new(
+    pub fn from_thread_fn(thread_fn: ThreadFn, stack_addr: u32, stack_limit: u32) -> Self {
+        Self::from_thread_fn_with(thread_fn, stack_addr, stack_limit, ThreadAttrs::default())
+    }
+
+    pub fn from_thread_fn_with(thread_fn: ThreadFn, stack_addr: u32, stack_limit: u32, attrs: ThreadAttrs) -> Self {
+        defmt::trace!("thread: from_thread_fn: stack addr: {:#x} limit: {:#x} priv: {} fp: {}",
+            stack_addr, stack_limit, attrs.privileged, attrs.fp);
+        let t = Self::new(
             stack_addr,
-            0,
+            stack_limit,
+            attrs.prio,
             thread_fn as u32,
+            attrs.privileged,
+            attrs.fp,
+            #[cfg(feature = "trustzone")]
+            attrs.nonsecure,
+            #[cfg(not(feature = "trustzone"))]
             false,
-            false,
-        )
+        );
+        Thread { cpu_budget: attrs.cpu_budget, ..t }
     }
 
     pub fn get_ctrl(&self) -> u32 {
-        if self.privileged {
-            0x2
-        } else {
-            0x3
-        }
+        self.context.control
+    }
+
+    /// End a wait: back to `Ready`, or `Suspended` if that was asked for meanwhile.
+    pub fn make_ready(&mut self) {
+        self.state = if self.suspend_requested { ThreadState::Suspended } else { ThreadState::Ready };
+        self.suspend_requested = false;
+        self.deadline = None;
     }
 }
 
@@ -114,7 +295,7 @@ pub extern "C" fn thread_trampoline(f: ThreadFn) {
     f();
 
     // if it ever returns, trap into exit
-    muos_syscall::exit_thread();
+    let _ = muos_syscall::exit_thread();
 
     defmt::trace!("thread trampoline end");
 }
@@ -0,0 +1,90 @@
+//! `wait_many`: one thread queued on several objects at once.
+//!
+//! The waiter goes onto the queue of every object it names, one wait slot per
+//! spec, and blocks as `BlockReason::Many`.  Whichever object gets to it first
+//! completes that slot through `object::complete`, which pulls the thread off
+//! all its other queues, so at most one of the waits ever takes effect.
+//!
+//! A notification has no object to queue on: the thread waits on a queue of
+//! its own, `Objects::notify_waiters`, which `notify` looks at.
+
+use muos_syscall::{SyscallError, UserPtr, UserSlice, WaitKind, WaitSpec, MAX_WAIT_SPECS};
+use crate::object::{self, with_objects, Objects};
+use crate::scheduler::{with_scheduler, Scheduler};
+use crate::thread::BlockReason;
+use crate::waitqueue::WaitQueue;
+use crate::{futex, latch, mutex, notify, rwlock, stream};
+
+/// Check one spec: `Ok(true)` if it's satisfied right now (and whatever it
+/// stands for has been taken).
+fn ready(o: &mut Objects, sched: &mut dyn Scheduler, spec: &WaitSpec, tid: usize) -> Result<bool, SyscallError> {
+    match spec.kind().ok_or(SyscallError::InvalidArgument)? {
+        WaitKind::Mutex => mutex::try_lock_for(o, spec.handle(), tid),
+        WaitKind::RwRead => rwlock::try_lock_for(o, spec.handle(), tid, false),
+        WaitKind::RwWrite => rwlock::try_lock_for(o, spec.handle(), tid, true),
+        WaitKind::Futex => Ok(!futex::would_block(UserPtr::new(spec.target as usize), spec.arg)?),
+        WaitKind::ThreadExit => match sched.thread_id(spec.handle()) {
+            Ok(t) if t == tid => Err(SyscallError::Deadlock),
+            Ok(_) => Ok(false),
+            Err(SyscallError::NoSuchThread) => Ok(true),
+            Err(e) => Err(e),
+        },
+        WaitKind::Latch => latch::is_open(o, spec.handle()),
+        WaitKind::StreamReadable => stream::readable(o, spec.handle()),
+        WaitKind::Notify => notify::poll(sched, tid, UserPtr::new(spec.target as usize), spec.arg),
+    }
+}
+
+/// The queue `spec` of thread `tid` goes on.
+pub(crate) fn queue<'a>(o: &'a mut Objects, spec: &WaitSpec, tid: usize) -> Result<&'a mut WaitQueue, SyscallError> {
+    match spec.kind().ok_or(SyscallError::InvalidArgument)? {
+        WaitKind::Mutex => mutex::queue(o, spec.handle()),
+        WaitKind::RwRead => rwlock::queue(o, spec.handle(), false),
+        WaitKind::RwWrite => rwlock::queue(o, spec.handle(), true),
+        WaitKind::Futex => futex::queue(o, spec.target as usize),
+        WaitKind::ThreadExit => Ok(&mut o.exit_waiters[spec.handle().index()]),
+        WaitKind::Latch => latch::queue(o, spec.handle()),
+        WaitKind::StreamReadable => stream::poll_queue(o, spec.handle()),
+        WaitKind::Notify => Ok(&mut o.notify_waiters[tid]),
+    }
+}
+
+/// `Some(index)` of a spec that's already satisfied, or `None` if the caller
+/// was blocked on all of them; `WouldBlock` instead with a timeout of 0.
+pub(crate) fn wait_many(specs: UserPtr<WaitSpec>, count: u32, timeout_ms: Option<usize>) -> Result<Option<usize>, SyscallError> {
+    let count = count as usize;
+    if count == 0 || count > MAX_WAIT_SPECS {
+        return Err(SyscallError::InvalidArgument);
+    }
+    let mut local = [WaitSpec { kind: 0, target: 0, arg: 0 }; MAX_WAIT_SPECS];
+    unsafe { UserSlice::new(specs.addr(), count).copy_to(&mut local)? };
+    let specs = &local[..count];
+
+    with_objects(|o| with_scheduler(|sched| {
+        let tid = sched.current_thread_id();
+        for (i, spec) in specs.iter().enumerate() {
+            if ready(o, sched, spec, tid)? {
+                return Ok(Some(i));
+            }
+        }
+        if timeout_ms == Some(0) {
+            return Err(SyscallError::WouldBlock);
+        }
+
+        for (slot, spec) in specs.iter().enumerate() {
+            match queue(o, spec, tid) {
+                Ok(q) => {
+                    q.insert(sched, (tid, slot));
+                    o.wait_specs[tid][slot] = Some(*spec);
+                }
+                Err(e) => {
+                    // e.g. out of futex queues: undo what's queued so far
+                    object::cancel_many(o, sched, tid, None);
+                    return Err(e);
+                }
+            }
+        }
+        sched.block_current_timeout(BlockReason::Many, timeout_ms);
+        Ok(None)
+    }))
+}
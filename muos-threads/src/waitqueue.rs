@@ -0,0 +1,122 @@
+//! Wait queues for kernel objects.
+//!
+//! A queue is only a head entry; the links live in the threads themselves
+//! (`Thread::wait_next`), so an object can have every thread waiting on it
+//! without any storage of its own.  An entry is a thread plus the slot of the
+//! wait it stands for: always 0, except for `wait_many`, which has a thread on
+//! several queues at once.  Waiters are kept by priority, FIFO among equals.
+//!
+//! A queued thread is `Blocked(BlockReason::Object(handle))` (or `Futex`, or
+//! `Many`), maybe with a deadline.  Waking goes through `object::complete` so
+//! a `wait_many` waiter leaves its other queues too; `object::expire_timeouts`
+//! takes timed out threads off their queues.
+
+use muos_syscall::SyscallError;
+use crate::scheduler::Scheduler;
+use crate::thread::{BlockReason, ThreadState};
+
+/// A queue entry: thread slot and wait slot.
+pub(crate) type Waiter = (usize, usize);
+
+pub(crate) struct WaitQueue {
+    head: Option<Waiter>,
+}
+
+impl WaitQueue {
+    pub(crate) const fn new() -> Self {
+        WaitQueue { head: None }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    /// Block the calling thread for `reason` and queue it here.
+    pub(crate) fn block_current(&mut self, sched: &mut dyn Scheduler, reason: BlockReason, timeout_ms: Option<usize>) {
+        sched.block_current_timeout(reason, timeout_ms);
+        let tid = sched.current_thread_id();
+        self.insert(sched, (tid, 0));
+    }
+
+    /// Queue a thread that is already blocked (and off any other queue) to
+    /// wait for `reason` instead, without a timeout.  With `error` set the
+    /// thread sees that instead of its eventual wake result.
+    pub(crate) fn requeue(&mut self, sched: &mut dyn Scheduler, tid: usize, reason: BlockReason, error: Option<SyscallError>) {
+        let t = sched.table_mut().threads[tid].as_mut().expect("requeue: no such thread");
+        t.state = ThreadState::Blocked(reason);
+        t.deadline = None;
+        t.wake_error = error;
+        self.insert(sched, (tid, 0));
+    }
+
+    fn next(sched: &mut dyn Scheduler, (tid, slot): Waiter) -> &mut Option<Waiter> {
+        &mut sched.table_mut().threads[tid].as_mut().unwrap().wait_next[slot]
+    }
+
+    fn prio(sched: &mut dyn Scheduler, (tid, _): Waiter) -> u32 {
+        sched.table_mut().threads[tid].as_ref().unwrap().prio
+    }
+
+    /// Queue wait `slot` of a thread that's already blocked.
+    pub(crate) fn insert(&mut self, sched: &mut dyn Scheduler, waiter: Waiter) {
+        let prio = Self::prio(sched, waiter);
+
+        // walk past everyone of the same or higher priority
+        let mut prev: Option<Waiter> = None;
+        let mut next = self.head;
+        while let Some(w) = next {
+            if Self::prio(sched, w) < prio {
+                break;
+            }
+            prev = Some(w);
+            next = *Self::next(sched, w);
+        }
+        *Self::next(sched, waiter) = next;
+        match prev {
+            Some(p) => *Self::next(sched, p) = Some(waiter),
+            None => self.head = Some(waiter),
+        }
+    }
+
+    /// The first waiter, left on the queue.
+    pub(crate) fn peek(&self) -> Option<Waiter> {
+        self.head
+    }
+
+    /// Take the first waiter off the queue; it stays blocked.
+    pub(crate) fn pop(&mut self, sched: &mut dyn Scheduler) -> Option<Waiter> {
+        let w = self.head?;
+        self.head = Self::next(sched, w).take();
+        Some(w)
+    }
+
+    /// Move `waiter` to where its thread's priority puts it now, if it's on
+    /// the queue.
+    pub(crate) fn reposition(&mut self, sched: &mut dyn Scheduler, waiter: Waiter) -> bool {
+        let queued = self.remove(sched, waiter);
+        if queued {
+            self.insert(sched, waiter);
+        }
+        queued
+    }
+
+    /// Take `waiter` off the queue, if it's on it.
+    pub(crate) fn remove(&mut self, sched: &mut dyn Scheduler, waiter: Waiter) -> bool {
+        let mut prev: Option<Waiter> = None;
+        let mut next = self.head;
+        while let Some(w) = next {
+            let after = *Self::next(sched, w);
+            if w == waiter {
+                match prev {
+                    Some(p) => *Self::next(sched, p) = after,
+                    None => self.head = after,
+                }
+                *Self::next(sched, w) = None;
+                return true;
+            }
+            prev = Some(w);
+            next = after;
+        }
+        false
+    }
+}
@@ -0,0 +1,64 @@
+//! Software watchdog on top of the hardware one.
+//!
+//! Threads opt in with `wdt_register(timeout_ms)` and then have to call
+//! `wdt_feed()` at least that often.  Every tick the kernel checks all
+//! registered threads and feeds the hardware watchdog only if none of them is
+//! late.  A late thread gets its id recorded as the reset reason (see
+//! `boot_info()`) and the system is reset; the hardware timeout backs this up
+//! for the case where the kernel itself stops ticking.
+//!
+//! The bookkeeping lives in the host-testable `muos-watchdog` crate; this is
+//! the kernel's instance of it.
+
+use core::cell::RefCell;
+use cortex_m::interrupt::{self, Mutex};
+use muos_syscall::SyscallError;
+use muos_watchdog::Watchdog;
+use crate::bootinfo::{self, ResetReason};
+use crate::scheduler::MAX_THREADS;
+
+pub use muos_watchdog::HardwareWatchdog;
+
+static WATCHDOG: Mutex<RefCell<Watchdog<MAX_THREADS>>> = Mutex::new(RefCell::new(Watchdog::new()));
+
+fn with_watchdog<F, R>(f: F) -> R
+    where
+        F: FnOnce(&mut Watchdog<MAX_THREADS>) -> R,
+{
+    interrupt::free(|cs| f(&mut WATCHDOG.borrow(cs).borrow_mut()))
+}
+
+/// Hand the hardware watchdog to the kernel and arm it with `hw_timeout_ms`,
+/// which has to be comfortably longer than a scheduler tick.
+pub fn init(hw: &'static mut dyn HardwareWatchdog, hw_timeout_ms: u32) {
+    with_watchdog(|w| w.start(hw, hw_timeout_ms));
+}
+
+pub(crate) fn register(tid: usize, timeout_ms: u32) -> Result<(), SyscallError> {
+    if timeout_ms == 0 {
+        return Err(SyscallError::InvalidArgument);
+    }
+    with_watchdog(|w| w.register(tid, timeout_ms as usize));
+    Ok(())
+}
+
+pub(crate) fn feed(tid: usize) -> Result<(), SyscallError> {
+    if with_watchdog(|w| w.feed(tid)) {
+        Ok(())
+    } else {
+        Err(SyscallError::InvalidArgument)
+    }
+}
+
+pub(crate) fn release_thread(tid: usize) {
+    with_watchdog(|w| w.unregister(tid));
+}
+
+/// Called from SysTick.
+pub(crate) fn tick(ms: usize) {
+    let result = with_watchdog(|w| w.tick(ms, |tid| bootinfo::record(ResetReason::Watchdog, tid as u32)));
+    if let Err(tid) = result {
+        defmt::error!("watchdog: thread {} missed its deadline, resetting", tid);
+        cortex_m::peripheral::SCB::sys_reset();
+    }
+}
@@ -0,0 +1,143 @@
+//! Deferred work ("bottom halves").
+//!
+//! An ISR calls `defer(f, arg)` to queue `f(arg)` and returns; the kernel
+//! worker thread, which outranks every user thread, runs the queued items in
+//! thread mode with interrupts enabled.  The queue is a bounded lock-free
+//! MPSC ring (Vyukov), so ISRs of different priorities can enqueue without
+//! masking each other; only waking the worker takes the scheduler lock.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use cortex_m::peripheral::SCB;
+use crate::scheduler::{spawn_thread_with, with_scheduler};
+use crate::thread::{BlockReason, ThreadAttrs};
+
+pub type WorkFn = fn(usize);
+
+pub const WORK_QUEUE_LEN: usize = 16; // power of two
+/// Worker priority; user threads should stay below this.
+pub const WORKER_PRIO: u32 = u32::MAX;
+
+#[derive(Copy, Clone)]
+pub struct WorkItem {
+    pub f: WorkFn,
+    pub arg: usize,
+}
+
+struct Slot {
+    seq: AtomicUsize,
+    item: UnsafeCell<MaybeUninit<WorkItem>>,
+}
+
+struct WorkQueue {
+    slots: [Slot; WORK_QUEUE_LEN],
+    head: AtomicUsize, // only the worker moves this
+    tail: AtomicUsize,
+    dropped: AtomicU32,
+}
+
+// slots are handed over through `seq`, see push/pop
+unsafe impl Sync for WorkQueue {}
+
+impl WorkQueue {
+    const fn new() -> Self {
+        let mut slots = [const { Slot { seq: AtomicUsize::new(0), item: UnsafeCell::new(MaybeUninit::uninit()) } }; WORK_QUEUE_LEN];
+        let mut i = 0;
+        while i < WORK_QUEUE_LEN {
+            slots[i].seq = AtomicUsize::new(i);
+            i += 1;
+        }
+        WorkQueue { slots, head: AtomicUsize::new(0), tail: AtomicUsize::new(0), dropped: AtomicU32::new(0) }
+    }
+
+    fn push(&self, item: WorkItem) -> Result<(), WorkItem> {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % WORK_QUEUE_LEN];
+            let seq = slot.seq.load(Ordering::Acquire);
+            let diff = seq.wrapping_sub(pos) as isize;
+            if diff == 0 {
+                match self.tail.compare_exchange_weak(pos, pos.wrapping_add(1), Ordering::Relaxed, Ordering::Relaxed) {
+                    Ok(_) => {
+                        unsafe { (*slot.item.get()).write(item) };
+                        slot.seq.store(pos.wrapping_add(1), Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(now) => pos = now,
+                }
+            } else if diff < 0 {
+                return Err(item); // full
+            } else {
+                pos = self.tail.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Single consumer: only ever called from the worker thread.
+    fn pop(&self) -> Option<WorkItem> {
+        let pos = self.head.load(Ordering::Relaxed);
+        let slot = &self.slots[pos % WORK_QUEUE_LEN];
+        if slot.seq.load(Ordering::Acquire) != pos.wrapping_add(1) {
+            return None;
+        }
+        let item = unsafe { (*slot.item.get()).assume_init_read() };
+        slot.seq.store(pos.wrapping_add(WORK_QUEUE_LEN), Ordering::Release);
+        self.head.store(pos.wrapping_add(1), Ordering::Relaxed);
+        Some(item)
+    }
+
+    fn is_empty(&self) -> bool {
+        let pos = self.head.load(Ordering::Relaxed);
+        self.slots[pos % WORK_QUEUE_LEN].seq.load(Ordering::Acquire) != pos.wrapping_add(1)
+    }
+}
+
+static QUEUE: WorkQueue = WorkQueue::new();
+static WORKER_TID: AtomicUsize = AtomicUsize::new(usize::MAX);
+
+/// Queue `f(arg)` for the worker thread. Safe to call from any ISR or
+/// privileged thread; hands the item back if the queue is full.
+pub fn defer(f: WorkFn, arg: usize) -> Result<(), WorkItem> {
+    QUEUE.push(WorkItem { f, arg }).inspect_err(|_| {
+        QUEUE.dropped.fetch_add(1, Ordering::Relaxed);
+    })?;
+
+    let tid = WORKER_TID.load(Ordering::Relaxed);
+    if tid != usize::MAX {
+        with_scheduler(|sched| sched.unblock(tid));
+        SCB::set_pendsv();
+    }
+    Ok(())
+}
+
+/// Number of items `defer` has turned away because the queue was full.
+pub fn dropped_work() -> u32 {
+    QUEUE.dropped.load(Ordering::Relaxed)
+}
+
+pub(crate) fn is_worker(tid: usize) -> bool {
+    WORKER_TID.load(Ordering::Relaxed) == tid
+}
+
+pub(crate) fn init_worker() {
+    spawn_thread_with(worker_thread, ThreadAttrs { prio: WORKER_PRIO, privileged: true, ..Default::default() });
+}
+
+fn worker_thread() {
+    WORKER_TID.store(with_scheduler(|sched| sched.current_thread_id()), Ordering::Relaxed);
+    loop {
+        while let Some(work) = QUEUE.pop() {
+            (work.f)(work.arg);
+        }
+        // re-check under the lock: a `defer` after this can't be missed, it
+        // finds us blocked and unblocks us
+        with_scheduler(|sched| {
+            if QUEUE.is_empty() {
+                sched.block_current(BlockReason::Work);
+            }
+        });
+        SCB::set_pendsv();
+        cortex_m::asm::isb();
+    }
+}
@@ -0,0 +1,214 @@
+//! Software watchdog on top of a hardware one.
+//!
+//! Threads register with a timeout and then have to feed at least that
+//! often.  Every tick the kernel calls `Watchdog::tick`, which feeds the
+//! hardware watchdog only if none of the registered threads is late.  A late
+//! thread is handed to the caller's `record` and the hardware is left
+//! hungry; the kernel then resets.  The hardware timeout backs this up for
+//! the case where the kernel itself stops ticking.
+//!
+//! Nothing here touches the chip: the hardware sits behind
+//! `HardwareWatchdog`, so the unit tests run on the host against a fake:
+//!
+//! ```text
+//! cargo test -p muos-watchdog --target x86_64-unknown-linux-gnu
+//! ```
+
+#![cfg_attr(not(test), no_std)]
+
+pub trait HardwareWatchdog: Send {
+    /// Arm the watchdog to reset the chip after `timeout_ms` without a feed.
+    fn start(&mut self, timeout_ms: u32);
+    fn feed(&mut self);
+}
+
+#[cfg(feature = "rp235x")]
+impl HardwareWatchdog for rp235x_hal::Watchdog {
+    fn start(&mut self, timeout_ms: u32) {
+        rp235x_hal::Watchdog::start(self, rp235x_hal::fugit::MicrosDurationU32::millis(timeout_ms));
+    }
+
+    fn feed(&mut self) {
+        rp235x_hal::Watchdog::feed(self);
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Entry {
+    timeout_ms: usize,
+    deadline: usize,
+}
+
+/// Deadlines of up to `N` threads, by slot, and the hardware they guard.
+pub struct Watchdog<const N: usize> {
+    entries: [Option<Entry>; N],
+    now: usize, // ms
+    hw: Option<&'static mut dyn HardwareWatchdog>,
+}
+
+impl<const N: usize> Watchdog<N> {
+    pub const fn new() -> Self {
+        Watchdog { entries: [None; N], now: 0, hw: None }
+    }
+
+    /// Take over `hw` and arm it with `timeout_ms`, which has to be
+    /// comfortably longer than a tick.
+    pub fn start(&mut self, hw: &'static mut dyn HardwareWatchdog, timeout_ms: u32) {
+        hw.start(timeout_ms);
+        self.hw = Some(hw);
+    }
+
+    pub fn register(&mut self, tid: usize, timeout_ms: usize) {
+        self.entries[tid] = Some(Entry { timeout_ms, deadline: self.now.wrapping_add(timeout_ms) });
+    }
+
+    pub fn unregister(&mut self, tid: usize) {
+        self.entries[tid] = None;
+    }
+
+    /// Push `tid`'s deadline out again; `false` if it isn't registered.
+    pub fn feed(&mut self, tid: usize) -> bool {
+        match self.entries[tid].as_mut() {
+            Some(e) => {
+                e.deadline = self.now.wrapping_add(e.timeout_ms);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Advance time by `ms` and feed the hardware if everyone is on time.
+    /// Otherwise `record` the first thread past its deadline and return it
+    /// in `Err`, leaving the hardware unfed.
+    pub fn tick(&mut self, ms: usize, record: impl FnOnce(usize)) -> Result<(), usize> {
+        self.now = self.now.wrapping_add(ms);
+        let late = self.entries.iter().position(|e| matches!(e, Some(e) if self.now.wrapping_sub(e.deadline) as isize > 0));
+        match late {
+            Some(tid) => {
+                record(tid);
+                Err(tid)
+            }
+            None => {
+                if let Some(hw) = self.hw.as_mut() {
+                    hw.feed();
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<const N: usize> Default for Watchdog<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Default)]
+    struct FakeHw {
+        timeout_ms: Arc<AtomicU32>,
+        feeds: Arc<AtomicU32>,
+    }
+
+    impl HardwareWatchdog for FakeHw {
+        fn start(&mut self, timeout_ms: u32) {
+            self.timeout_ms.store(timeout_ms, Ordering::SeqCst);
+        }
+
+        fn feed(&mut self) {
+            self.feeds.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// A started watchdog and its fake's feed counter.
+    fn started() -> (Watchdog<4>, Arc<AtomicU32>) {
+        let hw = FakeHw::default();
+        let feeds = hw.feeds.clone();
+        let timeout = hw.timeout_ms.clone();
+        let mut w = Watchdog::new();
+        w.start(Box::leak(Box::new(hw)), 500);
+        assert_eq!(timeout.load(Ordering::SeqCst), 500);
+        (w, feeds)
+    }
+
+    fn no_record(tid: usize) {
+        panic!("thread {} recorded as stalled", tid);
+    }
+
+    #[test]
+    fn healthy_threads_keep_the_hardware_fed() {
+        let (mut w, feeds) = started();
+        w.register(0, 30);
+        w.register(2, 100);
+        for i in 1..=50 {
+            assert_eq!(w.tick(10, no_record), Ok(()));
+            if i % 3 == 0 {
+                assert!(w.feed(0));
+            }
+            if i % 10 == 0 {
+                assert!(w.feed(2));
+            }
+        }
+        assert_eq!(feeds.load(Ordering::SeqCst), 50);
+    }
+
+    #[test]
+    fn stalled_thread_stops_the_feeding_and_is_recorded() {
+        let (mut w, feeds) = started();
+        w.register(1, 30);
+        w.register(3, 1000);
+        let mut recorded = None;
+        let mut ticks = 0;
+        let stalled = loop {
+            ticks += 1;
+            assert!(w.feed(3));
+            match w.tick(10, |tid| recorded = Some(tid)) {
+                Ok(()) => assert!(ticks < 10, "thread 1 never counted as late"),
+                Err(tid) => break tid,
+            }
+        };
+        assert_eq!(stalled, 1);
+        assert_eq!(recorded, Some(1));
+        // on time up to its deadline, unfed from the first tick past it
+        assert_eq!(ticks, 4);
+        assert_eq!(feeds.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn unregistered_threads_are_left_alone() {
+        let (mut w, feeds) = started();
+        assert!(!w.feed(0));
+        w.register(0, 20);
+        w.unregister(0);
+        assert!(!w.feed(0));
+        for _ in 0..10 {
+            assert_eq!(w.tick(10, no_record), Ok(()));
+        }
+        assert_eq!(feeds.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn deadlines_survive_the_clock_wrapping() {
+        let (mut w, _) = started();
+        w.now = usize::MAX - 25;
+        w.register(0, 30);
+        for _ in 0..3 {
+            assert_eq!(w.tick(10, no_record), Ok(()));
+        }
+        assert_eq!(w.tick(10, |_| {}), Err(0));
+    }
+
+    #[test]
+    fn works_without_hardware() {
+        let mut w: Watchdog<2> = Watchdog::new();
+        w.register(1, 10);
+        assert_eq!(w.tick(10, no_record), Ok(()));
+        assert_eq!(w.tick(10, |_| {}), Err(1));
+    }
+}